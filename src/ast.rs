@@ -0,0 +1,215 @@
+//! The abstract syntax tree produced by the CalcLang parser.
+//!
+//! A program is a sequence of statements. Expressions are kept as a tree
+//! of unary and binary operations so that precedence and associativity decided by
+//! the parser are explicit in the structure.
+
+use std::fmt;
+use std::rc::Rc;
+
+use value::Value;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Statement {
+    Assignment(String, Expression),
+    Expression(Expression),
+    /// A condition, the statements run when it holds, and those run when
+    /// it does not (empty without an `else`).
+    If(Expression, Vec<Statement>, Vec<Statement>),
+    While(Expression, Vec<Statement>),
+    /// Counts a variable from the first bound up to the second, inclusive.
+    For(String, Expression, Expression, Vec<Statement>),
+    Break,
+    Continue,
+    /// Defines a function, replacing any earlier one with the same name.
+    Function(Rc<Function>),
+    Return(Expression),
+    /// Writes the items one after another, then a newline.
+    Print(Vec<PrintItem>),
+    Quit,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PrintItem {
+    Text(String),
+    Value(Expression),
+}
+
+/// A user-defined function. The body is shared, so that calling the
+/// function does not copy it.
+#[derive(Debug, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub parameters: Vec<String>,
+    pub body: Vec<Statement>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expression {
+    Literal(Value),
+    Variable(String),
+    Unary(UnaryOperator, Box<Expression>),
+    Binary(Box<Expression>, BinaryOperator, Box<Expression>),
+    Call(String, Vec<Expression>),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UnaryOperator {
+    Negation,
+    Plus,
+    Not,
+    BitNot,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BinaryOperator {
+    Addition,
+    Subtraction,
+    Multiplication,
+    Division,
+    Modulus,
+    Exponent,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+}
+
+impl Statement {
+    /// Whether this is an `if` that an `else` could still follow, because
+    /// neither it nor the last `else if` of its chain has one. An empty
+    /// `else { }` looks the same as none, so it counts as missing.
+    pub fn accepts_else(&self) -> bool {
+        match *self {
+            Statement::If(_, _, ref else_branch) => match else_branch[..] {
+                [] => true,
+                [ref nested] => nested.accepts_else(),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Statement::Assignment(ref name, ref value) => write!(f, "{} = {};", name, value),
+            Statement::Expression(ref value) => write!(f, "{};", value),
+            Statement::If(ref condition, ref then_branch, ref else_branch) => {
+                write!(f, "if {} ", condition)?;
+                write_block(f, then_branch)?;
+                if !else_branch.is_empty() {
+                    write!(f, " else ")?;
+                    write_block(f, else_branch)?;
+                }
+                Ok(())
+            },
+            Statement::While(ref condition, ref body) => {
+                write!(f, "while {} ", condition)?;
+                write_block(f, body)
+            },
+            Statement::For(ref name, ref from, ref to, ref body) => {
+                write!(f, "for {} = {} to {} ", name, from, to)?;
+                write_block(f, body)
+            },
+            Statement::Break => write!(f, "break;"),
+            Statement::Continue => write!(f, "continue;"),
+            Statement::Function(ref function) => {
+                write!(f, "fn {}({}) ", function.name, function.parameters.join(", "))?;
+                write_block(f, &function.body)
+            },
+            Statement::Return(ref value) => write!(f, "return {};", value),
+            Statement::Print(ref items) => {
+                write!(f, "print")?;
+                for (i, item) in items.iter().enumerate() {
+                    write!(f, "{}", if i == 0 { " " } else { ", " })?;
+                    match *item {
+                        PrintItem::Text(ref text) => write!(f, "{:?}", text)?,
+                        PrintItem::Value(ref value) => write!(f, "{}", value)?,
+                    }
+                }
+                write!(f, ";")
+            },
+            Statement::Quit => write!(f, "quit"),
+        }
+    }
+}
+
+fn write_block(f: &mut fmt::Formatter, statements: &[Statement]) -> fmt::Result {
+    write!(f, "{{")?;
+    for statement in statements {
+        write!(f, " {}", statement)?;
+    }
+    write!(f, " }}")
+}
+
+/// Operations are written fully parenthesized, so the grouping
+/// chosen by the parser is visible when a tree is printed.
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Expression::Literal(ref value) => write!(f, "{}", value),
+            Expression::Variable(ref name) => write!(f, "{}", name),
+            Expression::Unary(op, ref operand) => write!(f, "({}{})", op, operand),
+            Expression::Binary(ref left, op, ref right) => write!(f, "({} {} {})", left, op, right),
+            Expression::Call(ref name, ref arguments) => {
+                write!(f, "{}(", name)?;
+                for (i, argument) in arguments.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", argument)?;
+                }
+                write!(f, ")")
+            },
+        }
+    }
+}
+
+impl fmt::Display for UnaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UnaryOperator::Negation => write!(f, "-"),
+            UnaryOperator::Plus => write!(f, "+"),
+            UnaryOperator::Not => write!(f, "!"),
+            UnaryOperator::BitNot => write!(f, "~"),
+        }
+    }
+}
+
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match *self {
+            BinaryOperator::Addition => "+",
+            BinaryOperator::Subtraction => "-",
+            BinaryOperator::Multiplication => "*",
+            BinaryOperator::Division => "/",
+            BinaryOperator::Modulus => "%",
+            BinaryOperator::Exponent => "^",
+            BinaryOperator::Equal => "==",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::Less => "<",
+            BinaryOperator::LessEqual => "<=",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::GreaterEqual => ">=",
+            BinaryOperator::And => "&&",
+            BinaryOperator::Or => "||",
+            BinaryOperator::BitAnd => "&",
+            BinaryOperator::BitOr => "|",
+            BinaryOperator::BitXor => "^^",
+            BinaryOperator::ShiftLeft => "<<",
+            BinaryOperator::ShiftRight => ">>",
+        };
+        write!(f, "{}", symbol)
+    }
+}
@@ -0,0 +1,405 @@
+//! The built-in functions of CalcLang, called like user-defined ones:
+//!
+//! abs(x), min(x, ...), max(x, ...): keep the kind of number they are given
+//! floor(x), ceil(x), round(x): the nearest integer below, above, or either
+//!     side of x; halves round away from zero
+//! sqrt(x): exact when x is the square of an integer or fraction, otherwise
+//!     a float
+//! ln(x), log10(x), sin(x), cos(x), tan(x): floats, with angles in radians
+//! gcd(a, b), lcm(a, b), factorial(n), choose(n, k): integers only
+//!
+//! An argument outside a function's domain, such as `sqrt(-1)` or `ln(0)`,
+//! is a `RuntimeError::Domain` rather than a NaN or infinity.
+
+use std::fmt;
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{FromPrimitive, One, Signed, ToPrimitive, Zero};
+
+use ast::{BinaryOperator, UnaryOperator};
+use interpreter::RuntimeError;
+use value::{self, NumericMode, Value};
+
+/// The largest `n` accepted by `factorial`, and by `choose` for the smaller
+/// of `k` and `n - k`, so that a typo does not stall the calculator.
+const MAX_FACTORIAL: u64 = 10_000;
+
+/// How many arguments a function takes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+}
+
+pub struct Builtin {
+    pub name: &'static str,
+    pub arity: Arity,
+    pub function: fn(&[Value]) -> Result<Value, RuntimeError>,
+}
+
+const BUILTINS: &[Builtin] = &[
+    Builtin{name: "abs", arity: Arity::Exactly(1), function: abs},
+    Builtin{name: "min", arity: Arity::AtLeast(1), function: min},
+    Builtin{name: "max", arity: Arity::AtLeast(1), function: max},
+    Builtin{name: "sqrt", arity: Arity::Exactly(1), function: sqrt},
+    Builtin{name: "floor", arity: Arity::Exactly(1), function: floor},
+    Builtin{name: "ceil", arity: Arity::Exactly(1), function: ceil},
+    Builtin{name: "round", arity: Arity::Exactly(1), function: round},
+    Builtin{name: "ln", arity: Arity::Exactly(1), function: ln},
+    Builtin{name: "log10", arity: Arity::Exactly(1), function: log10},
+    Builtin{name: "sin", arity: Arity::Exactly(1), function: sin},
+    Builtin{name: "cos", arity: Arity::Exactly(1), function: cos},
+    Builtin{name: "tan", arity: Arity::Exactly(1), function: tan},
+    Builtin{name: "gcd", arity: Arity::Exactly(2), function: gcd},
+    Builtin{name: "lcm", arity: Arity::Exactly(2), function: lcm},
+    Builtin{name: "factorial", arity: Arity::Exactly(1), function: factorial},
+    Builtin{name: "choose", arity: Arity::Exactly(2), function: choose},
+];
+
+pub fn lookup(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+impl Arity {
+    pub fn accepts(self, count: usize) -> bool {
+        match self {
+            Arity::Exactly(expected) => count == expected,
+            Arity::AtLeast(minimum) => count >= minimum,
+        }
+    }
+}
+
+fn abs(arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let x = number("abs", &arguments[0])?;
+    if x.is_negative() {
+        value::unary(UnaryOperator::Negation, x)
+    } else {
+        Ok(x.clone())
+    }
+}
+
+fn min(arguments: &[Value]) -> Result<Value, RuntimeError> {
+    extreme("min", arguments, BinaryOperator::Less)
+}
+
+fn max(arguments: &[Value]) -> Result<Value, RuntimeError> {
+    extreme("max", arguments, BinaryOperator::Greater)
+}
+
+/// Picks the first argument that no other beats under `op`.
+fn extreme(name: &str, arguments: &[Value], op: BinaryOperator) -> Result<Value, RuntimeError> {
+    let mut best = number(name, &arguments[0])?;
+    for argument in &arguments[1..] {
+        let argument = number(name, argument)?;
+        if value::binary(op, argument, best, NumericMode::Integer)? == Value::Boolean(true) {
+            best = argument;
+        }
+    }
+    Ok(best.clone())
+}
+
+fn sqrt(arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let x = number("sqrt", &arguments[0])?;
+    if x.is_negative() {
+        return Err(domain("sqrt", "negative numbers"));
+    }
+    if let Value::Float(x) = *x {
+        return Value::from_float(x.sqrt());
+    }
+    let x = x.to_rational();
+    let (numer, denom) = (x.numer().sqrt(), x.denom().sqrt());
+    if &numer * &numer == *x.numer() && &denom * &denom == *x.denom() {
+        return Ok(Value::from_rational(BigRational::new(numer, denom)));
+    }
+    match Value::Rational(x.clone()).to_float() {
+        float if float.is_normal() => Value::from_float(float.sqrt()),
+        _ => Value::from_float(big_sqrt(x.numer()) / big_sqrt(x.denom())),
+    }
+}
+
+/// The square root of a non-negative integer too large or too precise for
+/// a float, taken before converting so that it only overflows when the
+/// root itself does.
+fn big_sqrt(x: &BigInt) -> f64 {
+    match x.to_f64() {
+        Some(float) if float.is_finite() => float.sqrt(),
+        _ => x.sqrt().to_f64().unwrap_or(f64::INFINITY),
+    }
+}
+
+fn floor(arguments: &[Value]) -> Result<Value, RuntimeError> {
+    rounded("floor", &arguments[0], f64::floor, |x| x.floor())
+}
+
+fn ceil(arguments: &[Value]) -> Result<Value, RuntimeError> {
+    rounded("ceil", &arguments[0], f64::ceil, |x| x.ceil())
+}
+
+fn round(arguments: &[Value]) -> Result<Value, RuntimeError> {
+    rounded("round", &arguments[0], f64::round, |x| x.round())
+}
+
+/// Rounds `x` to an integer, with `float` for floats and `exact` for
+/// fractions. Integers are returned unchanged.
+fn rounded(name: &str,
+           x: &Value,
+           float: fn(f64) -> f64,
+           exact: fn(&BigRational) -> BigRational) -> Result<Value, RuntimeError> {
+    match *number(name, x)? {
+        Value::Float(x) => BigInt::from_f64(float(x)).map(Value::from_big).ok_or(RuntimeError::Overflow),
+        Value::Rational(ref x) => Ok(Value::from_rational(exact(x))),
+        ref integer => Ok(integer.clone()),
+    }
+}
+
+fn ln(arguments: &[Value]) -> Result<Value, RuntimeError> {
+    logarithm("ln", &arguments[0], f64::ln)
+}
+
+fn log10(arguments: &[Value]) -> Result<Value, RuntimeError> {
+    logarithm("log10", &arguments[0], f64::log10)
+}
+
+fn logarithm(name: &str, x: &Value, log: fn(f64) -> f64) -> Result<Value, RuntimeError> {
+    let not_positive = || domain(name, "numbers that are not positive");
+    match *number(name, x)? {
+        Value::Float(x) if x > 0.0 => Value::from_float(log(x)),
+        Value::Float(_) => Err(not_positive()),
+        ref exact => {
+            let fraction = exact.to_rational();
+            if !fraction.is_positive() {
+                return Err(not_positive());
+            }
+            match exact.to_float() {
+                float if float.is_normal() => Value::from_float(log(float)),
+                _ => Value::from_float(big_log(fraction.numer(), log) - big_log(fraction.denom(), log)),
+            }
+        },
+    }
+}
+
+/// The logarithm of a positive integer of any size: `log(x)` is
+/// `log(x >> k) + k * log(2)`, with `k` leaving 64 significant bits.
+fn big_log(x: &BigInt, log: fn(f64) -> f64) -> f64 {
+    let shift = x.bits().saturating_sub(64);
+    let mantissa = (x >> shift).to_f64().unwrap_or(f64::NAN);
+    log(mantissa) + shift as f64 * log(2.0)
+}
+
+fn sin(arguments: &[Value]) -> Result<Value, RuntimeError> {
+    Value::from_float(number("sin", &arguments[0])?.to_float().sin())
+}
+
+fn cos(arguments: &[Value]) -> Result<Value, RuntimeError> {
+    Value::from_float(number("cos", &arguments[0])?.to_float().cos())
+}
+
+fn tan(arguments: &[Value]) -> Result<Value, RuntimeError> {
+    Value::from_float(number("tan", &arguments[0])?.to_float().tan())
+}
+
+/// The greatest common divisor, which is never negative; `gcd(0, 0)` is 0.
+fn gcd(arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let a = integer("gcd", &arguments[0])?;
+    let b = integer("gcd", &arguments[1])?;
+    Ok(Value::from_big(big_gcd(a, b)))
+}
+
+/// The least common multiple, which is never negative; it is 0 when either
+/// argument is.
+fn lcm(arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let a = integer("lcm", &arguments[0])?;
+    let b = integer("lcm", &arguments[1])?;
+    if a.is_zero() || b.is_zero() {
+        return Ok(Value::Integer(0));
+    }
+    let divisor = big_gcd(a.clone(), b.clone());
+    Ok(Value::from_big((a / divisor * b).abs()))
+}
+
+fn big_gcd(mut a: BigInt, mut b: BigInt) -> BigInt {
+    while !b.is_zero() {
+        let remainder = &a % &b;
+        a = b;
+        b = remainder;
+    }
+    a.abs()
+}
+
+fn factorial(arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let n = integer("factorial", &arguments[0])?;
+    if n.is_negative() {
+        return Err(domain("factorial", "negative numbers"));
+    }
+    let n = small_count(&n)?;
+    let mut product = BigInt::one();
+    for i in 2..=n {
+        product *= i;
+    }
+    Ok(Value::from_big(product))
+}
+
+/// The number of ways to pick `k` items from `n`, which is 0 when `k` is
+/// negative or greater than `n`.
+fn choose(arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let n = integer("choose", &arguments[0])?;
+    let k = integer("choose", &arguments[1])?;
+    if n.is_negative() {
+        return Err(domain("choose", "a negative number of items"));
+    }
+    if k.is_negative() || k > n {
+        return Ok(Value::Integer(0));
+    }
+    let k = small_count(&k.clone().min(&n - &k))?;
+    // Each partial product is itself a binomial coefficient, so the division
+    // is always exact.
+    let mut result = BigInt::one();
+    for i in 0..k {
+        result = result * (&n - i) / (i + 1);
+    }
+    Ok(Value::from_big(result))
+}
+
+/// Converts a count for `factorial` or `choose`, refusing ones so large
+/// that the result would take too long to compute.
+fn small_count(count: &BigInt) -> Result<u64, RuntimeError> {
+    match count.to_u64() {
+        Some(count) if count <= MAX_FACTORIAL => Ok(count),
+        _ => Err(RuntimeError::Overflow),
+    }
+}
+
+/// Checks that an argument to `name` is a number.
+fn number<'a>(name: &str, x: &'a Value) -> Result<&'a Value, RuntimeError> {
+    if x.is_number() {
+        Ok(x)
+    } else {
+        Err(RuntimeError::TypeMismatch(format!("'{}' cannot be applied to {}", name, x.type_name())))
+    }
+}
+
+/// Checks that an argument to `name` is an integer.
+fn integer(name: &str, x: &Value) -> Result<BigInt, RuntimeError> {
+    if x.is_integer() {
+        Ok(x.to_big())
+    } else {
+        Err(RuntimeError::TypeMismatch(format!("'{}' needs integers, found {}", name, x.type_name())))
+    }
+}
+
+fn domain(name: &str, what: &str) -> RuntimeError {
+    RuntimeError::Domain(format!("'{}' is not defined for {}", name, what))
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (prefix, count) = match *self {
+            Arity::Exactly(count) => ("", count),
+            Arity::AtLeast(count) => ("at least ", count),
+        };
+        write!(f, "{}{} argument{}", prefix, count, if count == 1 { "" } else { "s" })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::Statement;
+    use interpreter::Interpreter;
+    use parser::Parser;
+    use scanner::Scanner;
+
+    fn evaluate_in(mode: NumericMode, source: &str) -> Result<Value, RuntimeError> {
+        let mut s = Scanner::new(source);
+        s.scan().unwrap();
+        let program = Parser::new(s.output()).parse().unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_mode(mode);
+        match program[..] {
+            [Statement::Expression(ref expression)] => interpreter.evaluate(expression),
+            _ => panic!("{:?} is not a single expression", source),
+        }
+    }
+
+    fn evaluate(source: &str) -> Result<Value, RuntimeError> {
+        evaluate_in(NumericMode::Integer, source)
+    }
+
+    fn float(value: Result<Value, RuntimeError>) -> f64 {
+        match value {
+            Ok(Value::Float(value)) => value,
+            other => panic!("expected a float, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn arguments_outside_the_domain_are_errors() {
+        assert_eq!(evaluate("sqrt(-1);"), Err(RuntimeError::Domain("'sqrt' is not defined for negative numbers".to_string())));
+        assert_eq!(evaluate("ln(0);"), Err(RuntimeError::Domain("'ln' is not defined for numbers that are not positive".to_string())));
+        assert_eq!(evaluate("log10(-0.5);"), Err(RuntimeError::Domain("'log10' is not defined for numbers that are not positive".to_string())));
+        assert_eq!(evaluate("factorial(-1);"), Err(RuntimeError::Domain("'factorial' is not defined for negative numbers".to_string())));
+        assert_eq!(evaluate("sqrt(true);"), Err(RuntimeError::TypeMismatch("'sqrt' cannot be applied to boolean".to_string())));
+        assert_eq!(evaluate("gcd(1.5, 2);"), Err(RuntimeError::TypeMismatch("'gcd' needs integers, found float".to_string())));
+    }
+
+    #[test]
+    fn square_roots_are_exact_when_they_can_be() {
+        assert_eq!(evaluate("sqrt(16);"), Ok(Value::Integer(4)));
+        assert_eq!(evaluate_in(NumericMode::Rational, "sqrt(4/9);"), Ok(Value::Rational(BigRational::new(2.into(), 3.into()))));
+        assert_eq!(evaluate("sqrt(2.25);"), Ok(Value::Float(1.5)));
+        assert_eq!(float(evaluate("sqrt(2);")), 2f64.sqrt());
+    }
+
+    #[test]
+    fn big_arguments_do_not_overflow() {
+        assert!((float(evaluate("ln(2 ^ 1100);")) - 1100.0 * 2f64.ln()).abs() < 1e-9);
+        assert!((float(evaluate("log10(10 ^ 400);")) - 400.0).abs() < 1e-9);
+        assert!((float(evaluate("sqrt(2 ^ 2001);")) / (2f64.sqrt() * 2f64.powi(1000)) - 1.0).abs() < 1e-12);
+        assert!((float(evaluate_in(NumericMode::Rational, "ln(1 / 10 ^ 400);")) + 400.0 * 10f64.ln()).abs() < 1e-9);
+        assert_eq!(float(evaluate("ln(1e-310);")), 1e-310f64.ln());
+        assert_eq!(float(evaluate("log10(5e-324);")), 5e-324f64.log10());
+    }
+
+    #[test]
+    fn rounding_goes_to_an_integer() {
+        assert_eq!(evaluate("round(-2.5);"), Ok(Value::Integer(-3)));
+        assert_eq!(evaluate("round(2.5);"), Ok(Value::Integer(3)));
+        assert_eq!(evaluate("floor(-2.5);"), Ok(Value::Integer(-3)));
+        assert_eq!(evaluate("ceil(-2.5);"), Ok(Value::Integer(-2)));
+        assert_eq!(evaluate_in(NumericMode::Rational, "round(-7 / 2);"), Ok(Value::Integer(-4)));
+    }
+
+    #[test]
+    fn integer_functions() {
+        assert_eq!(evaluate("gcd(0, 0);"), Ok(Value::Integer(0)));
+        assert_eq!(evaluate("gcd(12, -18);"), Ok(Value::Integer(6)));
+        assert_eq!(evaluate("lcm(4, 6);"), Ok(Value::Integer(12)));
+        assert_eq!(evaluate("lcm(0, 6);"), Ok(Value::Integer(0)));
+        assert_eq!(evaluate("choose(3, 5);"), Ok(Value::Integer(0)));
+        assert_eq!(evaluate("choose(5, -1);"), Ok(Value::Integer(0)));
+        assert_eq!(evaluate("choose(50, 25);"), Ok(Value::Integer(126_410_606_437_752)));
+        assert_eq!(evaluate("factorial(0);"), Ok(Value::Integer(1)));
+        assert_eq!(evaluate("factorial(20);"), Ok(Value::Integer(2_432_902_008_176_640_000)));
+        assert_eq!(evaluate("factorial(10001);"), Err(RuntimeError::Overflow));
+    }
+
+    #[test]
+    fn min_max_and_abs_keep_the_kind_of_number() {
+        assert_eq!(evaluate("min(3, 1.5, 2);"), Ok(Value::Float(1.5)));
+        assert_eq!(evaluate("max(4);"), Ok(Value::Integer(4)));
+        assert_eq!(evaluate("abs(-5);"), Ok(Value::Integer(5)));
+        assert_eq!(evaluate("abs(-9223372036854775807 - 1);"), Ok(Value::BigInteger(BigInt::from(i64::MAX) + 1)));
+    }
+
+    #[test]
+    fn calls_need_the_right_number_of_arguments() {
+        let error = |name: &str, expected, found| Err(RuntimeError::WrongArgumentCount{name: name.to_string(), expected, found});
+        assert_eq!(evaluate("sqrt(1, 2);"), error("sqrt", Arity::Exactly(1), 2));
+        assert_eq!(evaluate("gcd(1);"), error("gcd", Arity::Exactly(2), 1));
+        assert_eq!(evaluate("max();"), error("max", Arity::AtLeast(1), 0));
+        assert_eq!(evaluate("nope(1);"), Err(RuntimeError::UndefinedFunction("nope".to_string())));
+        assert_eq!(RuntimeError::WrongArgumentCount{name: "max".to_string(), expected: Arity::AtLeast(1), found: 0}.to_string(),
+                   "'max' takes at least 1 argument, but got 0");
+    }
+}
@@ -0,0 +1,81 @@
+//! Formats error messages against the source they refer to, with a caret
+//! line underneath the offending span:
+//!
+//! ```text
+//! error: unexpected character '$' at line 1, column 5
+//!   |
+//! 1 | x = $3;
+//!   |     ^
+//! ```
+
+use std::iter;
+
+use scanner::Span;
+
+pub fn render(source: &str, span: Span, message: &str) -> String {
+    let text = source.lines().nth(span.line - 1).unwrap_or("");
+    let gutter = span.line.to_string();
+    let padding = " ".repeat(gutter.len());
+    // Underline at least one column, but never past the end of the line.
+    let available = text.chars().count().saturating_sub(span.column - 1);
+    let width = source.get(span.start..span.end).map_or(1, |s| s.chars().count()).min(available).max(1);
+    // Tabs before the caret are kept, so it lines up however they display.
+    let indent: String = text.chars().chain(iter::repeat(' '))
+        .take(span.column - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    format!("error: {} at {}\n{} |\n{} | {}\n{} | {}{}",
+            message, span,
+            padding,
+            gutter, text,
+            padding, indent, "^".repeat(width))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scanner::Scanner;
+
+    fn first_scan_error(source: &str) -> String {
+        let mut s = Scanner::new(source);
+        let e = s.scan().unwrap_err().remove(0);
+        render(source, e.span, &e.to_string())
+    }
+
+    #[test]
+    fn the_caret_is_under_the_span() {
+        assert_eq!(first_scan_error("x = $3;"),
+                   "error: unexpected character '$' at line 1, column 5\n  |\n1 | x = $3;\n  |     ^");
+    }
+
+    #[test]
+    fn an_empty_span_still_gets_a_caret() {
+        let span = Span{start: 3, end: 3, line: 1, column: 4};
+        assert_eq!(render("1 +", span, "unexpected end of input"),
+                   "error: unexpected end of input at line 1, column 4\n  |\n1 | 1 +\n  |    ^");
+    }
+
+    #[test]
+    fn the_underline_stops_at_the_end_of_the_line() {
+        assert_eq!(first_scan_error("x = \"open\nmore\n"),
+                   "error: unterminated string at line 1, column 5\n  |\n1 | x = \"open\n  |     ^^^^^");
+    }
+
+    #[test]
+    fn the_gutter_widens_with_the_line_number() {
+        assert_eq!(first_scan_error(&format!("{}y = 1 $ 2;", "\n".repeat(11))),
+                   "error: unexpected character '$' at line 12, column 7\n   |\n12 | y = 1 $ 2;\n   |       ^");
+    }
+
+    #[test]
+    fn tabs_before_the_caret_are_kept() {
+        assert_eq!(first_scan_error("\tx = $3;"),
+                   "error: unexpected character '$' at line 1, column 6\n  |\n1 | \tx = $3;\n  | \t    ^");
+    }
+
+    #[test]
+    fn carriage_returns_are_not_part_of_the_line() {
+        assert_eq!(first_scan_error("x = 1;\r\ny = $;\r\n"),
+                   "error: unexpected character '$' at line 2, column 5\n  |\n2 | y = $;\n  |     ^");
+    }
+}
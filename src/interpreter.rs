@@ -0,0 +1,484 @@
+//! The evaluator portion of CalcLang compiler.
+//! Walks the tree built by the parser and executes each statement in turn.
+//! The arithmetic itself lives in the `value` module.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use ast::{BinaryOperator, Expression, Function, PrintItem, Statement};
+use builtins::{self, Arity};
+use value::{self, NumericMode, Value};
+
+/// How many times a single loop may run by default before it is stopped.
+pub const DEFAULT_MAX_ITERATIONS: u64 = 1_000_000;
+
+/// How deeply statements and expressions may nest while running, counting
+/// every block, operand and function call on the way down. Deep recursion
+/// stops here with an error instead of overflowing the stack.
+pub const MAX_DEPTH: usize = 10_000;
+
+/// The stack a thread needs to run the interpreter up to `MAX_DEPTH`. The
+/// deepest recursion measured, a call inside `print` inside a `for` loop,
+/// takes about 5 KiB a level in a debug build and under 1 KiB in a release
+/// build, so 8 KiB a level leaves room to spare either way.
+pub const STACK_SIZE: usize = MAX_DEPTH * 8 * 1024;
+
+pub struct Interpreter {
+    environment: Environment,
+    functions: HashMap<String, Rc<Function>>,
+    mode: NumericMode,
+    places: Option<usize>,
+    max_iterations: Option<u64>,
+    /// How many levels of `execute` and `evaluate` are in progress.
+    depth: usize,
+    /// Where `print` and bare expression statements write.
+    output: Box<dyn Write>,
+}
+
+/// Storage for variables, by name. Each function call gets a frame of its
+/// own for its parameters and the variables it assigns; a name not found
+/// there is looked up among the globals, never in the caller's frame.
+pub struct Environment {
+    globals: HashMap<String, Value>,
+    frames: Vec<HashMap<String, Value>>,
+}
+
+/// How a run of statements finished.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Status {
+    Finished,
+    Quit,
+}
+
+/// How control leaves a statement.
+#[derive(Debug, PartialEq)]
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+    Return(Value),
+    Quit,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum RuntimeError {
+    UndefinedVariable(String),
+    DivisionByZero,
+    Overflow,
+    NotANumber,
+    TypeMismatch(String),
+    NegativeShift,
+    IterationLimit(u64),
+    UndefinedFunction(String),
+    WrongArgumentCount { name: String, expected: Arity, found: usize },
+    /// A function finished without reaching `return`.
+    MissingReturn(String),
+    RecursionLimit(usize),
+    /// A built-in function was given an argument outside its domain.
+    Domain(String),
+    /// Writing the output failed.
+    Output(String),
+}
+
+impl Environment {
+    pub fn new() -> Environment {
+        Environment{
+            globals: HashMap::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.frames.last().and_then(|frame| frame.get(name)).or_else(|| self.globals.get(name))
+    }
+
+    /// Assigns in the innermost call's frame, or globally outside any call.
+    pub fn set(&mut self, name: &str, value: Value) {
+        let scope = self.frames.last_mut().unwrap_or(&mut self.globals);
+        scope.insert(name.to_string(), value);
+    }
+
+    fn push(&mut self, frame: HashMap<String, Value>) {
+        self.frames.push(frame);
+    }
+
+    fn pop(&mut self) {
+        self.frames.pop();
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter::with_output(Box::new(io::stdout()))
+    }
+
+    /// Creates an interpreter that writes its results to `output` rather
+    /// than standard output.
+    pub fn with_output(output: Box<dyn Write>) -> Interpreter {
+        Interpreter{
+            environment: Environment::new(),
+            functions: HashMap::new(),
+            mode: NumericMode::Integer,
+            places: None,
+            max_iterations: Some(DEFAULT_MAX_ITERATIONS),
+            depth: 0,
+            output,
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: NumericMode) {
+        self.mode = mode;
+    }
+
+    /// Prints fractions as decimals rounded to `places` digits, rather than
+    /// as `n/d`.
+    pub fn set_places(&mut self, places: Option<usize>) {
+        self.places = places;
+    }
+
+    /// Limits how many times any one loop may run; `None` removes the limit.
+    pub fn set_max_iterations(&mut self, max_iterations: Option<u64>) {
+        self.max_iterations = max_iterations;
+    }
+
+    /// Executes statements in order, stopping early at `quit`.
+    /// Bare expression statements print their value.
+    pub fn run(&mut self, program: &[Statement]) -> Result<Status, RuntimeError> {
+        match self.execute(program)? {
+            Flow::Quit => Ok(Status::Quit),
+            _ => Ok(Status::Finished),
+        }
+    }
+
+    fn execute(&mut self, statements: &[Statement]) -> Result<Flow, RuntimeError> {
+        self.nested(|interpreter| interpreter.execute_statements(statements))
+    }
+
+    fn execute_statements(&mut self, statements: &[Statement]) -> Result<Flow, RuntimeError> {
+        for statement in statements {
+            let flow = match *statement {
+                Statement::Assignment(ref name, ref value) => {
+                    let value = self.evaluate(value)?;
+                    self.environment.set(name, value);
+                    Flow::Normal
+                },
+                Statement::Expression(ref value) => {
+                    let text = self.evaluate(value)?.format(self.places);
+                    self.write_line(&text)?;
+                    Flow::Normal
+                },
+                Statement::If(ref condition, ref then_branch, ref else_branch) => {
+                    if self.condition(condition, "if")? {
+                        self.execute(then_branch)?
+                    } else {
+                        self.execute(else_branch)?
+                    }
+                },
+                Statement::While(ref condition, ref body) => self.while_loop(condition, body)?,
+                Statement::For(ref name, ref from, ref to, ref body) => self.for_loop(name, from, to, body)?,
+                Statement::Break => Flow::Break,
+                Statement::Continue => Flow::Continue,
+                Statement::Function(ref function) => {
+                    self.functions.insert(function.name.clone(), function.clone());
+                    Flow::Normal
+                },
+                Statement::Return(ref value) => Flow::Return(self.evaluate(value)?),
+                Statement::Print(ref items) => {
+                    let mut line = String::new();
+                    for item in items {
+                        match *item {
+                            PrintItem::Text(ref text) => line.push_str(text),
+                            PrintItem::Value(ref value) => line.push_str(&self.evaluate(value)?.format(self.places)),
+                        }
+                    }
+                    self.write_line(&line)?;
+                    Flow::Normal
+                },
+                Statement::Quit => Flow::Quit,
+            };
+            if flow != Flow::Normal {
+                return Ok(flow);
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn write_line(&mut self, text: &str) -> Result<(), RuntimeError> {
+        writeln!(self.output, "{}", text).map_err(|e| RuntimeError::Output(e.to_string()))
+    }
+
+    /// Evaluates the condition of an `if` or `while`, which must be a boolean.
+    fn condition(&mut self, condition: &Expression, keyword: &str) -> Result<bool, RuntimeError> {
+        match self.evaluate(condition)? {
+            Value::Boolean(value) => Ok(value),
+            other => {
+                let message = format!("'{}' needs a boolean condition, found {}", keyword, other.type_name());
+                Err(RuntimeError::TypeMismatch(message))
+            },
+        }
+    }
+
+    fn while_loop(&mut self, condition: &Expression, body: &[Statement]) -> Result<Flow, RuntimeError> {
+        let mut iterations = 0;
+        while self.condition(condition, "while")? {
+            self.count_iteration(&mut iterations)?;
+            match self.execute(body)? {
+                Flow::Break => break,
+                flow @ Flow::Return(_) | flow @ Flow::Quit => return Ok(flow),
+                Flow::Normal | Flow::Continue => {},
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    /// Both bounds are evaluated once, up front. The variable is set from a
+    /// separate counter on every pass, so assigning to it in the body does
+    /// not change how many times the loop runs.
+    fn for_loop(&mut self, name: &str, from: &Expression, to: &Expression, body: &[Statement]) -> Result<Flow, RuntimeError> {
+        let mut counter = self.evaluate(from)?;
+        let to = self.evaluate(to)?;
+        for bound in &[&counter, &to] {
+            if !bound.is_integer() {
+                let message = format!("'for' bounds must be integers, found {}", bound.type_name());
+                return Err(RuntimeError::TypeMismatch(message));
+            }
+        }
+        let mut iterations = 0;
+        while value::binary(BinaryOperator::LessEqual, &counter, &to, self.mode)? == Value::Boolean(true) {
+            self.count_iteration(&mut iterations)?;
+            self.environment.set(name, counter.clone());
+            match self.execute(body)? {
+                Flow::Break => break,
+                flow @ Flow::Return(_) | flow @ Flow::Quit => return Ok(flow),
+                Flow::Normal | Flow::Continue => {},
+            }
+            counter = value::binary(BinaryOperator::Addition, &counter, &Value::Integer(1), self.mode)?;
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn count_iteration(&self, iterations: &mut u64) -> Result<(), RuntimeError> {
+        *iterations += 1;
+        match self.max_iterations {
+            Some(limit) if *iterations > limit => Err(RuntimeError::IterationLimit(limit)),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn evaluate(&mut self, expression: &Expression) -> Result<Value, RuntimeError> {
+        self.nested(|interpreter| interpreter.evaluate_expression(expression))
+    }
+
+    fn evaluate_expression(&mut self, expression: &Expression) -> Result<Value, RuntimeError> {
+        match *expression {
+            Expression::Literal(ref value) => Ok(value.clone()),
+            Expression::Variable(ref name) => self.environment.get(name).cloned()
+                .ok_or_else(|| RuntimeError::UndefinedVariable(name.clone())),
+            Expression::Unary(op, ref operand) => value::unary(op, &self.evaluate(operand)?),
+            Expression::Binary(ref left, op, ref right) => {
+                let left = self.evaluate(left)?;
+                // `false && x` and `true || x` are decided without evaluating x.
+                match (op, &left) {
+                    (BinaryOperator::And, &Value::Boolean(false)) | (BinaryOperator::Or, &Value::Boolean(true)) => return Ok(left),
+                    _ => {},
+                }
+                let right = self.evaluate(right)?;
+                value::binary(op, &left, &right, self.mode)
+            },
+            Expression::Call(ref name, ref arguments) => self.call(name, arguments),
+        }
+    }
+
+    /// Arguments are evaluated in the caller's scope, then bound to the
+    /// parameters in a new frame for the body. A user-defined function
+    /// hides a built-in one of the same name.
+    fn call(&mut self, name: &str, arguments: &[Expression]) -> Result<Value, RuntimeError> {
+        let function = match self.functions.get(name) {
+            Some(function) => function.clone(),
+            None => return self.call_builtin(name, arguments),
+        };
+        check_arity(name, Arity::Exactly(function.parameters.len()), arguments.len())?;
+        let mut frame = HashMap::new();
+        for (parameter, argument) in function.parameters.iter().zip(arguments) {
+            frame.insert(parameter.clone(), self.evaluate(argument)?);
+        }
+        self.environment.push(frame);
+        let flow = self.execute(&function.body);
+        self.environment.pop();
+        match flow? {
+            Flow::Return(value) => Ok(value),
+            _ => Err(RuntimeError::MissingReturn(name.to_string())),
+        }
+    }
+
+    /// Runs `f` one level deeper, failing once `MAX_DEPTH` levels are open.
+    fn nested<T, F: FnOnce(&mut Interpreter) -> Result<T, RuntimeError>>(&mut self, f: F) -> Result<T, RuntimeError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(RuntimeError::RecursionLimit(MAX_DEPTH));
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn call_builtin(&mut self, name: &str, arguments: &[Expression]) -> Result<Value, RuntimeError> {
+        let builtin = builtins::lookup(name).ok_or_else(|| RuntimeError::UndefinedFunction(name.to_string()))?;
+        check_arity(name, builtin.arity, arguments.len())?;
+        let mut values = Vec::with_capacity(arguments.len());
+        for argument in arguments {
+            values.push(self.evaluate(argument)?);
+        }
+        (builtin.function)(&values)
+    }
+}
+
+fn check_arity(name: &str, expected: Arity, found: usize) -> Result<(), RuntimeError> {
+    if expected.accepts(found) {
+        Ok(())
+    } else {
+        Err(RuntimeError::WrongArgumentCount{name: name.to_string(), expected, found})
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RuntimeError::UndefinedVariable(ref name) => write!(f, "variable '{}' is not defined", name),
+            RuntimeError::DivisionByZero => write!(f, "division by zero"),
+            RuntimeError::Overflow => write!(f, "result is too large"),
+            RuntimeError::NotANumber => write!(f, "result is not a real number"),
+            RuntimeError::TypeMismatch(ref message) => write!(f, "{}", message),
+            RuntimeError::NegativeShift => write!(f, "shift count is negative"),
+            RuntimeError::IterationLimit(limit) => write!(f, "loop stopped after {} iterations", limit),
+            RuntimeError::UndefinedFunction(ref name) => write!(f, "function '{}' is not defined", name),
+            RuntimeError::WrongArgumentCount{ref name, expected, found} =>
+                write!(f, "'{}' takes {}, but got {}", name, expected, found),
+            RuntimeError::MissingReturn(ref name) => write!(f, "function '{}' finished without returning a value", name),
+            RuntimeError::RecursionLimit(limit) => write!(f, "recursion or nesting deeper than {} levels", limit),
+            RuntimeError::Domain(ref message) => write!(f, "{}", message),
+            RuntimeError::Output(ref message) => write!(f, "cannot write output: {}", message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::thread;
+
+    use parser::Parser;
+    use scanner::Scanner;
+
+    /// A writer whose contents stay readable after the interpreter takes it.
+    #[derive(Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(bytes)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Runs `source` on a thread with the stack the REPL gives the
+    /// interpreter, after `configure` has adjusted its settings.
+    fn run_with(source: &'static str, configure: fn(&mut Interpreter)) -> Result<String, RuntimeError> {
+        thread::Builder::new().stack_size(STACK_SIZE).spawn(move || {
+            let mut s = Scanner::new(source);
+            s.scan().unwrap();
+            let program = Parser::new(s.output()).parse().unwrap();
+            let buffer = Buffer::default();
+            let mut interpreter = Interpreter::with_output(Box::new(buffer.clone()));
+            configure(&mut interpreter);
+            interpreter.run(&program)?;
+            let bytes = buffer.0.borrow().clone();
+            Ok(String::from_utf8(bytes).unwrap())
+        }).unwrap().join().unwrap()
+    }
+
+    fn result(source: &'static str) -> Result<String, RuntimeError> {
+        run_with(source, |_| {})
+    }
+
+    fn output(source: &'static str) -> String {
+        result(source).unwrap()
+    }
+
+    #[test]
+    fn deep_recursion_is_an_error() {
+        assert_eq!(result("fn f(n) { if n == 0 { return 0; } return f(n - 1) + 1; } f(950);"), Ok("950\n".to_string()));
+        assert_eq!(result("fn f(n) { while true { if n == 0 { return 0; } return f(n - 1) + 1; } } f(100000);"),
+                   Err(RuntimeError::RecursionLimit(MAX_DEPTH)));
+        assert_eq!(result("fn f(n) { return f(n + 1); } f(0);"), Err(RuntimeError::RecursionLimit(MAX_DEPTH)));
+        // The shape that takes the most stack a level, which `STACK_SIZE` is sized by.
+        assert_eq!(result("fn f(n) { for i = 1 to 1 { print f(n + 1); } } f(0);"),
+                   Err(RuntimeError::RecursionLimit(MAX_DEPTH)));
+    }
+
+    #[test]
+    fn break_and_continue_apply_to_the_innermost_loop() {
+        assert_eq!(output("i = 0; s = 0; while true { i++; if i > 10 { break; } if i % 2 == 0 { continue; } s += i; } s;"), "25\n");
+        assert_eq!(output("for i = 1 to 3 { for j = 1 to 3 { if j == 2 { break; } print i, j; } }"), "11\n21\n31\n");
+        assert_eq!(output("for i = 1 to 4 { if i == 2 { continue; } print i; }"), "1\n3\n4\n");
+    }
+
+    #[test]
+    fn assigning_the_loop_variable_does_not_change_the_count() {
+        assert_eq!(output("for i = 1 to 3 { print i; i = 10; }"), "1\n2\n3\n");
+        assert_eq!(output("n = 0; for i = 1 to 3 { i = 0; n++; } n;"), "3\n");
+        assert_eq!(output("n = 0; for i = 1 to 0 { n++; } n;"), "0\n");
+    }
+
+    #[test]
+    fn loops_stop_at_the_iteration_limit() {
+        let limit_five = |interpreter: &mut Interpreter| interpreter.set_max_iterations(Some(5));
+        assert_eq!(run_with("n = 0; while true { n++; }", limit_five), Err(RuntimeError::IterationLimit(5)));
+        assert_eq!(run_with("for i = 1 to 5 { } print \"done\";", limit_five), Ok("done\n".to_string()));
+        assert_eq!(run_with("for i = 1 to 6 { }", limit_five), Err(RuntimeError::IterationLimit(5)));
+        // The limit is per loop, not a total across loops.
+        assert_eq!(run_with("for i = 1 to 3 { for j = 1 to 5 { } } print i;", limit_five), Ok("3\n".to_string()));
+        assert_eq!(run_with("n = 0; while n < 20 { n++; } n;", |interpreter| interpreter.set_max_iterations(None)),
+                   Ok("20\n".to_string()));
+    }
+
+    #[test]
+    fn print_writes_its_items_on_one_line() {
+        assert_eq!(output(r#"t = 2 + 3; print "total = ", t; print "a\tb";"#), "total = 5\na\tb\n");
+    }
+
+    #[test]
+    fn expression_statements_write_their_value() {
+        assert_eq!(output("1 + 1; fn f(x) { return x * x; } f(4);"), "2\n16\n");
+        assert_eq!(output("-2 ^ 2; (-2) ^ 2; 2 ^ -1; -(-9223372036854775807 - 1);"), "-4\n4\n0\n9223372036854775808\n");
+    }
+
+    #[test]
+    fn logical_operators_short_circuit() {
+        assert_eq!(output("false && 1 / 0 == 1;"), "false\n");
+        assert_eq!(output("true || x;"), "true\n");
+        assert_eq!(result("x || true;"), Err(RuntimeError::UndefinedVariable("x".to_string())));
+    }
+
+    #[test]
+    fn booleans_and_numbers_do_not_mix() {
+        assert_eq!(result("true == 1;"),
+                   Err(RuntimeError::TypeMismatch("'==' cannot be applied to boolean and integer".to_string())));
+        assert_eq!(result("1 && true;"),
+                   Err(RuntimeError::TypeMismatch("'&&' cannot be applied to integer and boolean".to_string())));
+        assert_eq!(result("!1;"), Err(RuntimeError::TypeMismatch("'!' cannot be applied to integer".to_string())));
+    }
+
+    #[test]
+    fn comparisons_give_booleans() {
+        assert_eq!(output("!true; !(1 > 2); 1 == 1.0; 1 != 2;"), "false\ntrue\ntrue\ntrue\n");
+        // Comparisons bind tighter than equality.
+        assert_eq!(output("1 < 2 == true;"), "true\n");
+    }
+}
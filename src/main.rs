@@ -1,12 +1,268 @@
+extern crate num_bigint;
+extern crate num_rational;
+extern crate num_traits;
+
+mod ast;
+mod builtins;
+mod diagnostic;
+mod interpreter;
+mod parser;
 mod scanner;
-use scanner::Scanner;
+mod value;
+use ast::Statement;
+use interpreter::{Interpreter, Status};
+use parser::Parser;
+use scanner::{ScanError, ScanErrorKind, Scanner};
+use value::NumericMode;
+
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::process;
+use std::thread;
+
+const USAGE: &str = "usage: calculator_rs [--rational] [--places N] [--max-iterations N] [--tokens]";
+
+/// Settings of the REPL itself, as opposed to the interpreter.
+#[derive(Default)]
+struct Options {
+    /// List each input's tokens, comments included, instead of running it.
+    show_tokens: bool,
+}
+
+/// What the REPL should do after feeding it the pending input.
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Done,
+    Incomplete,
+    Quit,
+}
+
+/// The interpreter together with the input read for a statement that is not
+/// complete yet.
+struct Session {
+    interpreter: Interpreter,
+    options: Options,
+    buffer: String,
+}
+
+impl Session {
+    /// Adds a line of input and runs the buffer once it holds whole statements,
+    /// since a statement may span several lines.
+    fn feed(&mut self, line: &str) -> Outcome {
+        // Only the line terminator is dropped, since trailing spaces may
+        // belong to an open string.
+        self.buffer.push_str(line.trim_end_matches(&['\r', '\n'][..]));
+        self.buffer.push('\n');
+        let outcome = execute(&mut self.interpreter, &self.options, &self.buffer, false);
+        if outcome != Outcome::Incomplete {
+            self.buffer.clear();
+        }
+        outcome
+    }
 
+    /// Runs what is left in the buffer once the input has ended.
+    fn finish(&mut self) {
+        if !self.buffer.trim().is_empty() {
+            execute(&mut self.interpreter, &self.options, &self.buffer, true);
+        }
+        self.buffer.clear();
+    }
+}
 
 fn main() {
-    let program = "+ - 34 ; quit a 3 3 - - 1 * ^ 7";
-    let mut s = Scanner::new(program);
-    s.scan();
-    for line in s.output() {
-        println!("{:?}",line);
+    // The interpreter recurses as the program nests, so it gets a stack large
+    // enough to reach its own depth limit.
+    let repl = thread::Builder::new().stack_size(interpreter::STACK_SIZE).spawn(repl)
+        .expect("cannot start the interpreter thread");
+    // A panic has already been reported by the thread itself.
+    if repl.join().is_err() {
+        process::exit(101);
+    }
+}
+
+fn repl() {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut interpreter = Interpreter::new();
+    let options = match configure(&mut interpreter, env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2);
+        },
+    };
+    let mut session = Session{interpreter, options, buffer: String::new()};
+    loop {
+        prompt(if session.buffer.is_empty() { "> " } else { "... " });
+        let mut line = String::new();
+        match input.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {},
+            Err(e) => {
+                eprintln!("error reading input: {}", e);
+                break;
+            },
+        }
+        if let Outcome::Quit = session.feed(&line) {
+            return;
+        }
+    }
+    session.finish();
+}
+
+/// Applies the command-line options: `--rational` turns on exact fractions,
+/// `--places N` prints them as decimals to N places, and `--max-iterations N`
+/// changes how many times a loop may run (0 for no limit). `--tokens` is
+/// returned among the REPL's own options.
+fn configure<I: Iterator<Item = String>>(interpreter: &mut Interpreter, mut args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tokens" => options.show_tokens = true,
+            "--rational" => interpreter.set_mode(NumericMode::Rational),
+            "--places" => {
+                let places = args.next().and_then(|n| n.parse().ok()).ok_or("--places needs a number of digits")?;
+                interpreter.set_places(Some(places));
+            },
+            "--max-iterations" => {
+                let limit = args.next().and_then(|n| n.parse().ok()).ok_or("--max-iterations needs a number")?;
+                interpreter.set_max_iterations(if limit == 0 { None } else { Some(limit) });
+            },
+            _ => return Err(format!("unknown option '{}'", arg)),
+        }
+    }
+    Ok(options)
+}
+
+/// Scans, parses and runs `source`. Unless `at_end` is set, input that stops
+/// in the middle of a statement, or after an `if` that an `else` could still
+/// continue, is left for the next line to complete.
+fn execute(interpreter: &mut Interpreter, options: &Options, source: &str, at_end: bool) -> Outcome {
+    // A blank line gives up on a pending `else` or an open string, so a
+    // stray quote cannot swallow the rest of the session.
+    let blank_line = source.lines().last().is_none_or(|line| line.trim().is_empty());
+    let mut s = Scanner::new(source);
+    s.keep_comments(options.show_tokens);
+    if let Err(errors) = s.scan() {
+        let waiting = |e: &ScanError| e.is_incomplete() && !(blank_line && e.kind == ScanErrorKind::UnterminatedString);
+        if !at_end && errors.iter().all(waiting) {
+            return Outcome::Incomplete;
+        }
+        for e in errors {
+            eprintln!("{}", diagnostic::render(source, e.span, &e.to_string()));
+        }
+        return Outcome::Done;
+    }
+    if options.show_tokens {
+        for spanned in s.output() {
+            println!("{}: {}", spanned.span, spanned.token);
+        }
+        return Outcome::Done;
+    }
+    let statements = match Parser::new(s.output()).parse() {
+        // An `if` ending the input waits in case `else` starts the next line.
+        Ok(ref statements) if !at_end && !blank_line
+            && statements.last().is_some_and(Statement::accepts_else) => return Outcome::Incomplete,
+        Ok(statements) => statements,
+        Err(ref e) if e.is_incomplete() && !at_end => return Outcome::Incomplete,
+        Err(e) => {
+            match e.span() {
+                Some(span) => eprintln!("{}", diagnostic::render(source, span, &e.to_string())),
+                None => eprintln!("error: {}", e),
+            }
+            return Outcome::Done;
+        },
+    };
+    match interpreter.run(&statements) {
+        Ok(Status::Quit) => Outcome::Quit,
+        Ok(Status::Finished) => Outcome::Done,
+        Err(e) => {
+            eprintln!("runtime error: {}", e);
+            Outcome::Done
+        },
+    }
+}
+
+fn prompt(text: &str) {
+    print!("{}", text);
+    let _ = io::stdout().flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interpreter::RuntimeError;
+
+    fn arguments(list: &[&str]) -> Vec<String> {
+        list.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn run(interpreter: &mut Interpreter, source: &str) -> Result<(), RuntimeError> {
+        let mut s = Scanner::new(source);
+        s.scan().unwrap();
+        let statements = Parser::new(s.output()).parse().unwrap();
+        interpreter.run(&statements).map(|_| ())
+    }
+
+    fn session() -> Session {
+        Session{interpreter: Interpreter::with_output(Box::new(io::sink())), options: Options::default(), buffer: String::new()}
+    }
+
+    #[test]
+    fn bad_options_are_errors() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(configure(&mut interpreter, arguments(&["--fast"]).into_iter()).err(),
+                   Some("unknown option '--fast'".to_string()));
+        assert_eq!(configure(&mut interpreter, arguments(&["--places"]).into_iter()).err(),
+                   Some("--places needs a number of digits".to_string()));
+        assert_eq!(configure(&mut interpreter, arguments(&["--places", "two"]).into_iter()).err(),
+                   Some("--places needs a number of digits".to_string()));
+        assert_eq!(configure(&mut interpreter, arguments(&["--max-iterations", "-1"]).into_iter()).err(),
+                   Some("--max-iterations needs a number".to_string()));
+    }
+
+    #[test]
+    fn options_configure_the_interpreter() {
+        let mut interpreter = Interpreter::new();
+        let options = configure(&mut interpreter, arguments(&["--tokens", "--max-iterations", "3"]).into_iter()).unwrap();
+        assert!(options.show_tokens);
+        assert_eq!(run(&mut interpreter, "for i = 1 to 4 { }"), Err(RuntimeError::IterationLimit(3)));
+        // No limit at all, rather than a limit of zero.
+        configure(&mut interpreter, arguments(&["--max-iterations", "0"]).into_iter()).unwrap();
+        assert_eq!(run(&mut interpreter, "for i = 1 to 1000001 { }"), Ok(()));
+    }
+
+    #[test]
+    fn statements_can_span_lines() {
+        let mut session = session();
+        assert_eq!(session.feed("x = (1 +\n"), Outcome::Incomplete);
+        assert_eq!(session.feed("2);\n"), Outcome::Done);
+        assert_eq!(session.buffer, "");
+        assert_eq!(session.feed("/* a comment\n"), Outcome::Incomplete);
+        assert_eq!(session.feed("\n"), Outcome::Incomplete);
+        assert_eq!(session.feed("*/\n"), Outcome::Done);
+        assert_eq!(session.feed("x = ) 1;\n"), Outcome::Done);
+        assert_eq!(session.feed("quit\n"), Outcome::Quit);
+    }
+
+    #[test]
+    fn an_if_waits_for_an_else_until_a_blank_line() {
+        let mut session = session();
+        assert_eq!(session.feed("if 1 < 2 { y = 1; }\n"), Outcome::Incomplete);
+        assert_eq!(session.feed("else { y = 2; }\n"), Outcome::Done);
+        assert_eq!(session.feed("if 1 < 2 { y = 1; }\n"), Outcome::Incomplete);
+        assert_eq!(session.feed("  \r\n"), Outcome::Done);
+        assert_eq!(session.buffer, "");
+    }
+
+    #[test]
+    fn open_strings_keep_their_spaces_until_a_blank_line() {
+        let mut session = session();
+        assert_eq!(session.feed("s = \"a  \r\n"), Outcome::Incomplete);
+        assert_eq!(session.buffer, "s = \"a  \n");
+        assert_eq!(session.feed("b\";\n"), Outcome::Done);
+        assert_eq!(session.feed("s = \"open\n"), Outcome::Incomplete);
+        assert_eq!(session.feed("\n"), Outcome::Done);
+        assert_eq!(session.buffer, "");
     }
 }
@@ -0,0 +1,692 @@
+//! The parser portion of CalcLang compiler.
+//! Builds an abstract syntax tree from the tokens produced by the scanner.
+//!
+//! Grammar, from lowest to highest precedence:
+//!
+//! program    := statement*
+//! statement  := "quit" [";"] | if | while | for | ("break" | "continue") [";"]
+//!             | function | "return" expression ";" | print | assignment | expression ";" | ";"
+//! if         := "if" expression block ["else" (block | if)]
+//! while      := "while" expression block
+//! for        := "for" identifier "=" expression "to" expression block
+//! function   := "fn" identifier "(" [identifier ("," identifier)*] ")" block
+//! print      := "print" (string | expression) ("," (string | expression))* ";"
+//! block      := "{" statement* "}"
+//! assignment := identifier ("=" | "+=" | "-=" | "*=" | "/=" | "%=" | "^=") expression ";"
+//!             | identifier ("++" | "--") ";"
+//! expression := and ("||" and)*
+//! and        := equality ("&&" equality)*
+//! equality   := comparison (("==" | "!=") comparison)*
+//! comparison := bit_or (("<" | "<=" | ">" | ">=") bit_or)*
+//! bit_or     := bit_xor ("|" bit_xor)*
+//! bit_xor    := bit_and ("^^" bit_and)*
+//! bit_and    := shift ("&" shift)*
+//! shift      := sum (("<<" | ">>") sum)*
+//! sum        := term (("+" | "-") term)*
+//! term       := unary (("*" | "/" | "%") unary)*
+//! unary      := ("-" | "+" | "!" | "~") unary | power
+//! power      := primary ["^" unary]
+//! primary    := integer | float | boolean | call | identifier | "(" expression ")"
+//! call       := identifier "(" [expression ("," expression)*] ")"
+
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+
+use ast::{BinaryOperator, Expression, Function, PrintItem, Statement, UnaryOperator};
+use scanner::{Span, SpannedToken, Token};
+use value::Value;
+
+/// How deeply blocks and operands may nest, so that pathological input is
+/// an error rather than a stack overflow.
+const MAX_NESTING: usize = 256;
+
+pub struct Parser<'a> {
+    tokens: &'a [SpannedToken],
+    position: usize,
+    /// How many loops enclose the current statement.
+    loop_depth: usize,
+    /// Whether the current statement is inside a function body.
+    in_function: bool,
+    /// How many blocks and operands enclose the current position.
+    depth: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    UnexpectedToken { found: Token, span: Span, expected: &'static str },
+    UnexpectedEnd { expected: &'static str },
+    /// The `delimiter` at `open` was not closed; `found` is what came
+    /// instead of its closing partner.
+    Unclosed { open: Span, delimiter: Token, found: Option<Token> },
+    /// The closing `delimiter` at `span` had nothing to close.
+    Unmatched { span: Span, delimiter: Token },
+    /// A keyword at `span` used where it is not allowed: `break` or
+    /// `continue` outside a loop, `return` outside a function, or `fn` or
+    /// `quit` inside one.
+    Misplaced { keyword: Token, span: Span },
+    /// A function names the same parameter twice.
+    DuplicateParameter { name: String, span: Span },
+    /// Blocks or operands nested more than `MAX_NESTING` deep, at `span`.
+    TooDeep { span: Span },
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(tokens: &'a [SpannedToken]) -> Parser<'a> {
+        Parser{
+            tokens,
+            position: 0,
+            loop_depth: 0,
+            in_function: false,
+            depth: 0,
+        }
+    }
+
+    pub fn parse(&mut self) -> Result<Vec<Statement>, ParseError> {
+        let mut program = Vec::new();
+        loop {
+            match self.tokens.get(self.position) {
+                None => return Ok(program),
+                Some(spanned) if spanned.token == Token::RightBrace =>
+                    return Err(ParseError::Unmatched{span: spanned.span, delimiter: Token::RightBrace}),
+                // An empty statement.
+                Some(spanned) if spanned.token == Token::Terminator => self.position += 1,
+                Some(_) => program.push(self.statement()?),
+            }
+        }
+    }
+
+    fn statement(&mut self) -> Result<Statement, ParseError> {
+        if let Some(spanned) = self.tokens.get(self.position) {
+            let allowed = match spanned.token {
+                Token::Quit | Token::Fn => !self.in_function,
+                Token::Return => self.in_function,
+                _ => true,
+            };
+            if !allowed {
+                return Err(ParseError::Misplaced{keyword: spanned.token.clone(), span: spanned.span});
+            }
+        }
+        if self.eat(&Token::Quit) {
+            self.eat(&Token::Terminator);
+            return Ok(Statement::Quit);
+        }
+        if self.eat(&Token::If) {
+            return self.if_statement();
+        }
+        if self.eat(&Token::While) {
+            let condition = self.expression()?;
+            let body = self.loop_body()?;
+            return Ok(Statement::While(condition, body));
+        }
+        if self.eat(&Token::For) {
+            return self.for_statement();
+        }
+        if let Some(spanned) = self.tokens.get(self.position) {
+            if spanned.token == Token::Break || spanned.token == Token::Continue {
+                if self.loop_depth == 0 {
+                    return Err(ParseError::Misplaced{keyword: spanned.token.clone(), span: spanned.span});
+                }
+                self.position += 1;
+                self.eat(&Token::Terminator);
+                return Ok(if spanned.token == Token::Break { Statement::Break } else { Statement::Continue });
+            }
+        }
+        if self.eat(&Token::Fn) {
+            return self.function();
+        }
+        if self.eat(&Token::Return) {
+            let value = self.expression()?;
+            self.terminator()?;
+            return Ok(Statement::Return(value));
+        }
+        if self.eat(&Token::Print) {
+            let mut items = vec![self.print_item()?];
+            while self.eat(&Token::Comma) {
+                items.push(self.print_item()?);
+            }
+            self.terminator()?;
+            return Ok(Statement::Print(items));
+        }
+        if let Some(Token::Identifier(name)) = self.peek() {
+            if let Some(statement) = self.assignment(name)? {
+                return Ok(statement);
+            }
+        }
+        let value = self.expression()?;
+        self.terminator()?;
+        Ok(Statement::Expression(value))
+    }
+
+    /// Parses the rest of an `if` statement after the keyword.
+    fn if_statement(&mut self) -> Result<Statement, ParseError> {
+        let condition = self.expression()?;
+        let then_branch = self.block()?;
+        let else_branch = if !self.eat(&Token::Else) {
+            Vec::new()
+        } else if self.eat(&Token::If) {
+            vec![self.if_statement()?]
+        } else {
+            self.block()?
+        };
+        Ok(Statement::If(condition, then_branch, else_branch))
+    }
+
+    /// Parses the rest of a `for` statement after the keyword.
+    fn for_statement(&mut self) -> Result<Statement, ParseError> {
+        let name = match self.peek() {
+            Some(Token::Identifier(name)) => name.clone(),
+            _ => return Err(self.unexpected("a loop variable")),
+        };
+        self.position += 1;
+        self.expect(&Token::Assignment, "'='")?;
+        let from = self.expression()?;
+        self.expect(&Token::To, "'to'")?;
+        let to = self.expression()?;
+        let body = self.loop_body()?;
+        Ok(Statement::For(name, from, to, body))
+    }
+
+    fn print_item(&mut self) -> Result<PrintItem, ParseError> {
+        if let Some(Token::Str(text)) = self.peek() {
+            self.position += 1;
+            return Ok(PrintItem::Text(text.clone()));
+        }
+        self.expression().map(PrintItem::Value)
+    }
+
+    /// Parses the rest of a function definition after `fn`.
+    fn function(&mut self) -> Result<Statement, ParseError> {
+        let name = self.name("a function name")?;
+        let parameters = self.list(|parser| {
+            let name = parser.name("a parameter name")?;
+            Ok((name, parser.tokens[parser.position - 1].span))
+        })?;
+        let mut names: Vec<String> = Vec::new();
+        for (parameter, span) in parameters {
+            if names.contains(&parameter) {
+                return Err(ParseError::DuplicateParameter{name: parameter, span});
+            }
+            names.push(parameter);
+        }
+        // A loop around the definition does not extend into the body.
+        let loop_depth = mem::replace(&mut self.loop_depth, 0);
+        self.in_function = true;
+        let body = self.block();
+        self.in_function = false;
+        self.loop_depth = loop_depth;
+        Ok(Statement::Function(Rc::new(Function{name, parameters: names, body: body?})))
+    }
+
+    /// Parses a parenthesized, comma-separated list of `item`s.
+    fn list<T>(&mut self, item: fn(&mut Parser<'a>) -> Result<T, ParseError>) -> Result<Vec<T>, ParseError> {
+        let open = match self.tokens.get(self.position) {
+            Some(spanned) if spanned.token == Token::LeftParen => spanned.span,
+            _ => return Err(self.unexpected("'('")),
+        };
+        self.position += 1;
+        let mut items = Vec::new();
+        if self.eat(&Token::RightParen) {
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            if self.eat(&Token::RightParen) {
+                return Ok(items);
+            }
+            if !self.eat(&Token::Comma) {
+                return Err(ParseError::Unclosed{open, delimiter: Token::LeftParen, found: self.peek().cloned()});
+            }
+        }
+    }
+
+    /// Consumes an identifier and returns its name.
+    fn name(&mut self, expected: &'static str) -> Result<String, ParseError> {
+        match self.peek() {
+            Some(Token::Identifier(name)) => {
+                self.position += 1;
+                Ok(name.clone())
+            },
+            _ => Err(self.unexpected(expected)),
+        }
+    }
+
+    /// Parses a block in which `break` and `continue` are allowed.
+    fn loop_body(&mut self) -> Result<Vec<Statement>, ParseError> {
+        self.loop_depth += 1;
+        let body = self.block();
+        self.loop_depth -= 1;
+        body
+    }
+
+    fn block(&mut self) -> Result<Vec<Statement>, ParseError> {
+        self.nested(Parser::block_statements)
+    }
+
+    fn block_statements(&mut self) -> Result<Vec<Statement>, ParseError> {
+        let open = match self.tokens.get(self.position) {
+            Some(spanned) if spanned.token == Token::LeftBrace => spanned.span,
+            _ => return Err(self.unexpected("'{'")),
+        };
+        self.position += 1;
+        let mut statements = Vec::new();
+        loop {
+            match self.peek() {
+                Some(&Token::RightBrace) => {
+                    self.position += 1;
+                    return Ok(statements);
+                },
+                None => return Err(ParseError::Unclosed{open, delimiter: Token::LeftBrace, found: None}),
+                Some(&Token::Terminator) => self.position += 1,
+                Some(_) => statements.push(self.statement()?),
+            }
+        }
+    }
+
+    /// Parses an assignment to `name` if one starts here. Compound forms are
+    /// lowered to a plain assignment: `x += e` becomes `x = x + e`, and `x++`
+    /// becomes `x = x + 1`.
+    fn assignment(&mut self, name: &str) -> Result<Option<Statement>, ParseError> {
+        let op = match self.peek_at(1) {
+            Some(&Token::Assignment) => None,
+            Some(&Token::AdditionAssignment) => Some(BinaryOperator::Addition),
+            Some(&Token::SubtractionAssignment) => Some(BinaryOperator::Subtraction),
+            Some(&Token::MultiplicationAssignment) => Some(BinaryOperator::Multiplication),
+            Some(&Token::DivisionAssignment) => Some(BinaryOperator::Division),
+            Some(&Token::ModulusAssignment) => Some(BinaryOperator::Modulus),
+            Some(&Token::ExponentAssignment) => Some(BinaryOperator::Exponent),
+            // `x + + ;` means nothing else, so it can be read as `x++;`.
+            Some(step) if (*step == Token::Addition || *step == Token::Subtraction)
+                && self.peek_at(2) == Some(step) && self.peek_at(3) == Some(&Token::Terminator) => {
+                self.position += 4;
+                let op = if *step == Token::Addition { BinaryOperator::Addition } else { BinaryOperator::Subtraction };
+                return Ok(Some(update(name, op, Expression::Literal(Value::Integer(1)))));
+            },
+            _ => return Ok(None),
+        };
+        self.position += 2;
+        let value = self.expression()?;
+        self.terminator()?;
+        Ok(Some(match op {
+            Some(op) => update(name, op, value),
+            None => Statement::Assignment(name.to_string(), value),
+        }))
+    }
+
+    fn expression(&mut self) -> Result<Expression, ParseError> {
+        self.left_associative(&[BinaryOperator::Or], Parser::and)
+    }
+
+    fn and(&mut self) -> Result<Expression, ParseError> {
+        self.left_associative(&[BinaryOperator::And], Parser::equality)
+    }
+
+    fn equality(&mut self) -> Result<Expression, ParseError> {
+        self.left_associative(&[BinaryOperator::Equal, BinaryOperator::NotEqual], Parser::comparison)
+    }
+
+    fn comparison(&mut self) -> Result<Expression, ParseError> {
+        self.left_associative(&[BinaryOperator::Less,
+                                BinaryOperator::LessEqual,
+                                BinaryOperator::Greater,
+                                BinaryOperator::GreaterEqual], Parser::bit_or)
+    }
+
+    fn bit_or(&mut self) -> Result<Expression, ParseError> {
+        self.left_associative(&[BinaryOperator::BitOr], Parser::bit_xor)
+    }
+
+    fn bit_xor(&mut self) -> Result<Expression, ParseError> {
+        self.left_associative(&[BinaryOperator::BitXor], Parser::bit_and)
+    }
+
+    fn bit_and(&mut self) -> Result<Expression, ParseError> {
+        self.left_associative(&[BinaryOperator::BitAnd], Parser::shift)
+    }
+
+    fn shift(&mut self) -> Result<Expression, ParseError> {
+        self.left_associative(&[BinaryOperator::ShiftLeft, BinaryOperator::ShiftRight], Parser::sum)
+    }
+
+    fn sum(&mut self) -> Result<Expression, ParseError> {
+        self.left_associative(&[BinaryOperator::Addition, BinaryOperator::Subtraction], Parser::term)
+    }
+
+    fn term(&mut self) -> Result<Expression, ParseError> {
+        self.left_associative(&[BinaryOperator::Multiplication,
+                                BinaryOperator::Division,
+                                BinaryOperator::Modulus], Parser::unary)
+    }
+
+    /// Parses a chain of operands separated by any of `operators`, grouping
+    /// from the left: `a - b - c` is `(a - b) - c`.
+    fn left_associative(&mut self,
+                        operators: &[BinaryOperator],
+                        operand: fn(&mut Parser<'a>) -> Result<Expression, ParseError>) -> Result<Expression, ParseError> {
+        let mut left = operand(self)?;
+        while let Some(op) = self.binary_operator(operators) {
+            let right = operand(self)?;
+            left = Expression::Binary(Box::new(left), op, Box::new(right));
+        }
+        Ok(left)
+    }
+
+    /// Every operand is parsed through here, including those in parentheses
+    /// and exponents, so this is where expression nesting is counted.
+    fn unary(&mut self) -> Result<Expression, ParseError> {
+        self.nested(Parser::signed)
+    }
+
+    /// A sign binds more loosely than `^`, so `-2 ^ 2` is `-(2 ^ 2)`.
+    fn signed(&mut self) -> Result<Expression, ParseError> {
+        let op = match self.peek() {
+            Some(&Token::Subtraction) => UnaryOperator::Negation,
+            Some(&Token::Addition) => UnaryOperator::Plus,
+            Some(&Token::Not) => UnaryOperator::Not,
+            Some(&Token::BitNot) => UnaryOperator::BitNot,
+            _ => return self.power(),
+        };
+        self.position += 1;
+        let operand = self.unary()?;
+        Ok(Expression::Unary(op, Box::new(operand)))
+    }
+
+    /// Exponentiation is right-associative: `2 ^ 3 ^ 2` is `2 ^ (3 ^ 2)`.
+    /// The exponent may carry its own sign, as in `2 ^ -1`.
+    fn power(&mut self) -> Result<Expression, ParseError> {
+        let base = self.primary()?;
+        if self.eat(&Token::Exponent) {
+            let exponent = self.unary()?;
+            return Ok(Expression::Binary(Box::new(base), BinaryOperator::Exponent, Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<Expression, ParseError> {
+        let expected = "a value or variable";
+        let expression = match self.peek() {
+            Some(&Token::Integer(value)) => Expression::Literal(Value::Integer(value)),
+            Some(Token::BigInteger(value)) => Expression::Literal(Value::BigInteger(value.clone())),
+            Some(&Token::Float(value)) => Expression::Literal(Value::Float(value)),
+            Some(&Token::Boolean(value)) => Expression::Literal(Value::Boolean(value)),
+            Some(&Token::LeftParen) => {
+                let open = self.tokens[self.position].span;
+                self.position += 1;
+                let inner = self.expression()?;
+                if !self.eat(&Token::RightParen) {
+                    return Err(ParseError::Unclosed{open, delimiter: Token::LeftParen, found: self.peek().cloned()});
+                }
+                return Ok(inner);
+            },
+            Some(Token::Identifier(name)) if self.peek_at(1) == Some(&Token::LeftParen) => {
+                self.position += 1;
+                let arguments = self.list(Parser::expression)?;
+                return Ok(Expression::Call(name.clone(), arguments));
+            },
+            Some(Token::Identifier(name)) => Expression::Variable(name.clone()),
+            _ => return Err(self.unexpected(expected)),
+        };
+        self.position += 1;
+        Ok(expression)
+    }
+
+    /// Consumes the next token if it is one of the given operators.
+    fn binary_operator(&mut self, allowed: &[BinaryOperator]) -> Option<BinaryOperator> {
+        let op = match self.peek() {
+            Some(&Token::Addition) => BinaryOperator::Addition,
+            Some(&Token::Subtraction) => BinaryOperator::Subtraction,
+            Some(&Token::Multiplication) => BinaryOperator::Multiplication,
+            Some(&Token::Division) => BinaryOperator::Division,
+            Some(&Token::Modulus) => BinaryOperator::Modulus,
+            Some(&Token::Exponent) => BinaryOperator::Exponent,
+            Some(&Token::Equal) => BinaryOperator::Equal,
+            Some(&Token::NotEqual) => BinaryOperator::NotEqual,
+            Some(&Token::Less) => BinaryOperator::Less,
+            Some(&Token::LessEqual) => BinaryOperator::LessEqual,
+            Some(&Token::Greater) => BinaryOperator::Greater,
+            Some(&Token::GreaterEqual) => BinaryOperator::GreaterEqual,
+            Some(&Token::And) => BinaryOperator::And,
+            Some(&Token::Or) => BinaryOperator::Or,
+            Some(&Token::BitAnd) => BinaryOperator::BitAnd,
+            Some(&Token::BitOr) => BinaryOperator::BitOr,
+            Some(&Token::BitXor) => BinaryOperator::BitXor,
+            Some(&Token::ShiftLeft) => BinaryOperator::ShiftLeft,
+            Some(&Token::ShiftRight) => BinaryOperator::ShiftRight,
+            _ => return None,
+        };
+        if !allowed.contains(&op) {
+            return None;
+        }
+        self.position += 1;
+        Some(op)
+    }
+
+    /// Runs `parse` one level deeper, failing once `MAX_NESTING` levels are open.
+    fn nested<T>(&mut self, parse: fn(&mut Parser<'a>) -> Result<T, ParseError>) -> Result<T, ParseError> {
+        if self.depth >= MAX_NESTING {
+            // Nesting this deep has consumed tokens, so there is a last one.
+            let spanned = self.tokens.get(self.position).or_else(|| self.tokens.last()).unwrap();
+            return Err(ParseError::TooDeep{span: spanned.span});
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&'a Token> {
+        self.tokens.get(self.position + offset).map(|spanned| &spanned.token)
+    }
+
+    /// Builds the error for the current token not being what was `expected`.
+    fn unexpected(&self, expected: &'static str) -> ParseError {
+        match self.tokens.get(self.position) {
+            Some(spanned) => ParseError::UnexpectedToken{found: spanned.token.clone(), span: spanned.span, expected},
+            None => ParseError::UnexpectedEnd{expected},
+        }
+    }
+
+    /// Consumes the next token if it equals `token`.
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Expects the `;` ending a statement. A `)` there closes nothing.
+    fn terminator(&mut self) -> Result<(), ParseError> {
+        if let Some(spanned) = self.tokens.get(self.position) {
+            if spanned.token == Token::RightParen {
+                return Err(ParseError::Unmatched{span: spanned.span, delimiter: Token::RightParen});
+            }
+        }
+        self.expect(&Token::Terminator, "';'")
+    }
+
+    fn expect(&mut self, token: &Token, expected: &'static str) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(found) if found == token => {
+                self.position += 1;
+                Ok(())
+            },
+            _ => Err(self.unexpected(expected)),
+        }
+    }
+}
+
+/// Builds `name = name op value`.
+fn update(name: &str, op: BinaryOperator, value: Expression) -> Statement {
+    let current = Expression::Variable(name.to_string());
+    Statement::Assignment(name.to_string(), Expression::Binary(Box::new(current), op, Box::new(value)))
+}
+
+impl ParseError {
+    /// Where the error was found, unless it was at the end of the input.
+    /// For an unclosed parenthesis this is the opening one.
+    pub fn span(&self) -> Option<Span> {
+        match *self {
+            ParseError::UnexpectedToken{span, ..} => Some(span),
+            ParseError::UnexpectedEnd{..} => None,
+            ParseError::Unclosed{open, ..} => Some(open),
+            ParseError::Unmatched{span, ..} => Some(span),
+            ParseError::Misplaced{span, ..} => Some(span),
+            ParseError::DuplicateParameter{span, ..} => Some(span),
+            ParseError::TooDeep{span} => Some(span),
+        }
+    }
+
+    /// Whether more input could still make the program valid.
+    pub fn is_incomplete(&self) -> bool {
+        matches!(*self, ParseError::UnexpectedEnd{..} | ParseError::Unclosed{found: None, ..})
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::UnexpectedToken{ref found, expected, ..} =>
+                write!(f, "expected {}, found {}", expected, found),
+            ParseError::UnexpectedEnd{expected} => write!(f, "expected {}, found end of input", expected),
+            ParseError::Unclosed{ref delimiter, ref found, ..} => {
+                let close = if *delimiter == Token::LeftBrace { Token::RightBrace } else { Token::RightParen };
+                write!(f, "unclosed {}: expected {}, found ", delimiter, close)?;
+                match *found {
+                    Some(ref found) => write!(f, "{}", found),
+                    None => write!(f, "end of input"),
+                }
+            },
+            ParseError::Unmatched{ref delimiter, ..} => write!(f, "unmatched {}", delimiter),
+            ParseError::Misplaced{ref keyword, ..} => match *keyword {
+                Token::Break | Token::Continue => write!(f, "{} outside of a loop", keyword),
+                Token::Return => write!(f, "{} outside of a function", keyword),
+                _ => write!(f, "{} inside a function", keyword),
+            },
+            ParseError::DuplicateParameter{ref name, ..} => write!(f, "parameter '{}' is declared twice", name),
+            ParseError::TooDeep{..} => write!(f, "nested more than {} levels deep", MAX_NESTING),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    use interpreter::STACK_SIZE;
+    use scanner::Scanner;
+
+    fn parse(source: &str) -> Result<Vec<Statement>, ParseError> {
+        let mut s = Scanner::new(source);
+        s.scan().unwrap();
+        Parser::new(s.output()).parse()
+    }
+
+    /// The fully parenthesized form of the one expression statement in `source`.
+    fn grouping(source: &str) -> String {
+        match parse(source).unwrap()[..] {
+            [Statement::Expression(ref expression)] => expression.to_string(),
+            ref other => panic!("{:?} is not a single expression: {:?}", source, other),
+        }
+    }
+
+    #[test]
+    fn operators_bind_by_precedence() {
+        assert_eq!(grouping("1 + 2 * 3;"), "(1 + (2 * 3))");
+        assert_eq!(grouping("1 * 2 + 3 % 4;"), "((1 * 2) + (3 % 4))");
+        assert_eq!(grouping("a || b && c == d < e | f ^^ g & h << i + j;"),
+                   "(a || (b && (c == (d < (e | (f ^^ (g & (h << (i + j)))))))))");
+    }
+
+    #[test]
+    fn binary_operators_group_from_the_left() {
+        assert_eq!(grouping("8 - 4 - 2;"), "((8 - 4) - 2)");
+        assert_eq!(grouping("8 / 4 * 2;"), "((8 / 4) * 2)");
+    }
+
+    #[test]
+    fn exponents_group_from_the_right() {
+        assert_eq!(grouping("2 ^ 3 ^ 2;"), "(2 ^ (3 ^ 2))");
+        assert_eq!(grouping("2 * 3 ^ 2;"), "(2 * (3 ^ 2))");
+    }
+
+    #[test]
+    fn parentheses_override_precedence() {
+        assert_eq!(grouping("(1 + 2) * 3;"), "((1 + 2) * 3)");
+        assert_eq!(grouping("((2 ^ 3)) ^ 2;"), "((2 ^ 3) ^ 2)");
+        assert_eq!(grouping("8 - (4 - (2 - 1));"), "(8 - (4 - (2 - 1)))");
+    }
+
+    #[test]
+    fn signs_bind_looser_than_exponents() {
+        assert_eq!(grouping("-2 ^ 2;"), "(-(2 ^ 2))");
+        assert_eq!(grouping("2 ^ -1;"), "(2 ^ (-1))");
+        assert_eq!(grouping("(-2) ^ 2;"), "((-2) ^ 2)");
+        assert_eq!(grouping("- - +x * 3;"), "((-(-(+x))) * 3)");
+        assert_eq!(grouping("1 - -1;"), "(1 - (-1))");
+        assert_eq!(grouping("!a && ~b;"), "((!a) && (~b))");
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        // Parsing runs on the REPL's interpreter thread, which has a larger
+        // stack than a test thread.
+        thread::Builder::new().stack_size(STACK_SIZE).spawn(|| {
+            let parens = format!("{}1{};", "(".repeat(20_000), ")".repeat(20_000));
+            assert_eq!(parse(&parens).unwrap_err().span(), Some(Span{start: 256, end: 257, line: 1, column: 257}));
+            let signs = format!("{}1;", "-".repeat(100_000));
+            assert!(matches!(parse(&signs), Err(ParseError::TooDeep{..})));
+            let powers = format!("2{};", " ^ 2".repeat(100_000));
+            assert!(matches!(parse(&powers), Err(ParseError::TooDeep{..})));
+            let blocks = format!("{}{}", "if true { ".repeat(1000), "}".repeat(1000));
+            assert!(matches!(parse(&blocks), Err(ParseError::TooDeep{..})));
+            let fine = format!("{}1{};", "(".repeat(200), ")".repeat(200));
+            assert_eq!(grouping(&fine), "1");
+        }).unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn an_unclosed_parenthesis_points_at_the_opening_one() {
+        let error = parse("x = (1 + (2 * 3);").unwrap_err();
+        assert_eq!(error, ParseError::Unclosed{open: Span{start: 4, end: 5, line: 1, column: 5},
+                                               delimiter: Token::LeftParen, found: Some(Token::Terminator)});
+        assert!(!error.is_incomplete());
+        let error = parse("x = (1 + 2").unwrap_err();
+        assert_eq!(error.span(), Some(Span{start: 4, end: 5, line: 1, column: 5}));
+        assert!(error.is_incomplete());
+    }
+
+    #[test]
+    fn a_stray_closing_parenthesis_is_unmatched() {
+        let error = parse("x = 1 + 2);").unwrap_err();
+        assert_eq!(error, ParseError::Unmatched{span: Span{start: 9, end: 10, line: 1, column: 10}, delimiter: Token::RightParen});
+        assert!(!error.is_incomplete());
+        assert_eq!(error.to_string(), "unmatched ')'");
+    }
+
+    #[test]
+    fn an_if_accepts_else_until_its_chain_has_one() {
+        let accepts_else = |source| parse(source).unwrap()[0].accepts_else();
+        assert!(accepts_else("if a { }"));
+        assert!(accepts_else("if a { } else if b { }"));
+        assert!(!accepts_else("if a { } else { x; }"));
+        assert!(!accepts_else("if a { } else if b { } else { x; }"));
+        assert!(!accepts_else("while a { }"));
+    }
+
+    #[test]
+    fn statements_need_terminators() {
+        assert_eq!(parse("x = 1 + 2;").unwrap().len(), 1);
+        assert_eq!(parse("x = 1"), Err(ParseError::UnexpectedEnd{expected: "';'"}));
+        assert!(parse("x = 1").unwrap_err().is_incomplete());
+        let error = parse("1 + ;").unwrap_err();
+        assert_eq!(error.span(), Some(Span{start: 4, end: 5, line: 1, column: 5}));
+        assert!(!error.is_incomplete());
+    }
+}
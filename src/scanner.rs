@@ -1,18 +1,83 @@
-/// The scanner portion of CalcLang compiler.
-/// Detects the following tokens:
-///
-/// Integer constant: one or more decimal digits
-/// Arithmetic operators: +, -, *, /, %, ^
-/// Assignment operator: =
-/// Semicolon: ;
-/// Variable name: a single letter (ignore case)
-/// The word "quit" (ignore case)
+//! The scanner portion of CalcLang compiler.
+//! Detects the following tokens:
+//!
+//! Integer constant: one or more decimal digits, of any length, or hexadecimal,
+//!     octal or binary digits after a 0x, 0o or 0b prefix; `_` may separate digits
+//! Float constant: digits with a fraction and/or exponent, as in 3.14, .5, 1e-9, 6.02E23
+//! Arithmetic operators: +, -, *, /, %, ^
+//! Assignment operators: =, +=, -=, *=, /=, %=, ^=
+//! Comparison operators: ==, !=, <, <=, >, >=
+//! Boolean operators: &&, ||, !
+//! Bitwise operators: &, |, ^^ (exclusive or), ~, <<, >>
+//! Semicolon: ;
+//! Comma: ,
+//! Parentheses: ( )
+//! Braces: { }
+//! String: text between double quotes, with the escapes \n, \t, \", \\ and
+//!     \u{...} (one to six hex digits naming a Unicode character)
+//! Comments: `#` or `//` to the end of the line, and `/* ... */`, which may
+//!     nest; skipped unless kept as `Token::Comment` (see `keep_comments`)
+//! Identifier: a letter or underscore, then letters, digits and underscores (ignore case)
+//! Keywords: "quit", "true", "false", "if", "else", "while", "for", "to",
+//!     "break", "continue", "fn", "return", "print" (ignore case), see `KEYWORDS`
+//!
+//! Whitespace: any Unicode whitespace separates tokens. Lines end at `\n` or
+//! `\r\n`, and spans count lines and columns accordingly.
+//!
+//! Anything else is reported as a `ScanError` rather than a token.
 
+use std::fmt;
+use std::str::Chars;
+
+use num_bigint::BigInt;
 
 pub struct Scanner {
     input: String,
-    output: Vec<Token>,
+    output: Vec<SpannedToken>,
+    errors: Vec<ScanError>,
     state: ScannerState,
+    keep_comments: bool,
+}
+
+/// Reserved words, matched against whole runs of letters after case folding.
+const KEYWORDS: &[(&str, Token)] = &[
+    ("quit", Token::Quit),
+    ("true", Token::Boolean(true)),
+    ("false", Token::Boolean(false)),
+    ("if", Token::If),
+    ("else", Token::Else),
+    ("while", Token::While),
+    ("for", Token::For),
+    ("to", Token::To),
+    ("break", Token::Break),
+    ("continue", Token::Continue),
+    ("fn", Token::Fn),
+    ("return", Token::Return),
+    ("print", Token::Print),
+];
+
+/// A region of the source: byte offsets `start..end`, plus the 1-based line
+/// and column (counted in characters) where it begins.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+/// Walks the input one character at a time, keeping track of where it is.
+struct Cursor<'a> {
+    chars: Chars<'a>,
+    offset: usize,
+    line: usize,
+    column: usize,
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -20,13 +85,17 @@ enum ScannerState {
     Idle,
     CharMode,
     IntMode,
-    QuitMode,
+    WordMode,
+    StringMode,
     Done,
 }
 
-#[derive(Debug, Eq, PartialEq)]
-enum Token {
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
     Integer(i64),
+    BigInteger(BigInt),
+    Float(f64),
+    Boolean(bool),
     Addition,
     Subtraction,
     Multiplication,
@@ -34,10 +103,72 @@ enum Token {
     Modulus,
     Exponent,
     Assignment,
+    AdditionAssignment,
+    SubtractionAssignment,
+    MultiplicationAssignment,
+    DivisionAssignment,
+    ModulusAssignment,
+    ExponentAssignment,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+    Not,
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    ShiftLeft,
+    ShiftRight,
     Terminator,
+    Comma,
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
     Quit,
-    Variable(char),
-    Unknown(char),
+    If,
+    Else,
+    While,
+    For,
+    To,
+    Break,
+    Continue,
+    Fn,
+    Return,
+    Print,
+    Identifier(String),
+    Str(String),
+    /// A comment as written, delimiters included. Only produced when the
+    /// scanner keeps comments.
+    Comment(String),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ScanError {
+    pub kind: ScanErrorKind,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ScanErrorKind {
+    UnexpectedCharacter(char),
+    InvalidDigit { digit: char, radix: u32 },
+    MissingDigits { radix: u32 },
+    /// A float literal too large to be represented, such as `1e400`.
+    FloatOutOfRange,
+    /// A backslash in a string followed by a character with no meaning there.
+    InvalidEscape(char),
+    /// A `\u{...}` escape that is malformed or names no character.
+    InvalidUnicodeEscape,
+    /// A `/*` with no matching `*/`; the span is the opening delimiter.
+    UnterminatedComment,
+    /// A string with no closing quote; the span runs to the end of the input.
+    UnterminatedString,
 }
 
 impl Scanner {
@@ -45,79 +176,628 @@ impl Scanner {
         Scanner{
             input: input.to_string(),
             output: Vec::new(),
+            errors: Vec::new(),
             state: ScannerState::Idle,
+            keep_comments: false,
         }
     }
 
-    pub fn scan(&mut self) {
+    /// Keeps comments in the output as `Token::Comment`, for tools that
+    /// need to reproduce the source, instead of skipping them.
+    pub fn keep_comments(&mut self, keep: bool) {
+        self.keep_comments = keep;
+    }
+
+    /// Tokenizes the whole input. Scanning carries on past a bad character
+    /// so that every problem in the input is reported at once.
+    pub fn scan(&mut self) -> Result<(), Vec<ScanError>> {
         self.state = ScannerState::CharMode;
-        let mut chars = self.input.chars().peekable();
-        while let Some(c) = chars.next() {
+        let mut chars = Cursor::new(&self.input);
+        loop {
+            let (start, line, column) = (chars.offset, chars.line, chars.column);
+            let c = match chars.next() {
+                Some(c) => c,
+                None => break,
+            };
             let tok: Option<Token> = match c {
-                '+' => Some(Token::Addition),
-                '-' => Some(Token::Subtraction),
-                '*' => Some(Token::Multiplication),
-                '/' => Some(Token::Division),
-                '%' => Some(Token::Modulus),
-                '^' => Some(Token::Exponent),
-                '=' => Some(Token::Assignment),
-                ';' => Some(Token::Terminator),
-                ' ' => None,
-                'q' => {
-                    let mut proceed = match chars.peek() {
-                        Some(&next) if next == 'u' => true,
-                        _   => false
-                    };
-                    if !proceed {
-                        Some(Token::Variable(c))
-                    } else {
+                '#' | '/' if c == '#' || chars.peek() == Some('/') => {
+                    while !chars.at_line_end() {
                         chars.next();
-                        proceed = match chars.peek() {
-                            Some(&next) if next == 'i' => true,
-                            _   => false
-                        };
-                        if !proceed {
-                            Some(Token::Variable(c))
-                        } else {
-                            chars.next();
-                            proceed = match chars.peek() {
-                                Some(&next) if next == 't' => true,
-                                _   => false
-                            };
-                            if !proceed {Some(Token::Variable(c))}
-                            else {
-                                chars.next();
-                                Some(Token::Quit)
-                            }
+                    }
+                    self.comment(start, chars.offset)
+                },
+                '/' if chars.eat('*') => {
+                    if block_comment(&mut chars) {
+                        self.comment(start, chars.offset)
+                    } else {
+                        let span = Span{start, end: start + 2, line, column};
+                        self.errors.push(ScanError{kind: ScanErrorKind::UnterminatedComment, span});
+                        None
+                    }
+                },
+                '+' => Some(if chars.eat('=') { Token::AdditionAssignment } else { Token::Addition }),
+                '-' => Some(if chars.eat('=') { Token::SubtractionAssignment } else { Token::Subtraction }),
+                '*' => Some(if chars.eat('=') { Token::MultiplicationAssignment } else { Token::Multiplication }),
+                '/' => Some(if chars.eat('=') { Token::DivisionAssignment } else { Token::Division }),
+                '%' => Some(if chars.eat('=') { Token::ModulusAssignment } else { Token::Modulus }),
+                '^' if chars.eat('^') => Some(Token::BitXor),
+                '^' => Some(if chars.eat('=') { Token::ExponentAssignment } else { Token::Exponent }),
+                '=' => Some(if chars.eat('=') { Token::Equal } else { Token::Assignment }),
+                '!' => Some(if chars.eat('=') { Token::NotEqual } else { Token::Not }),
+                '<' if chars.eat('<') => Some(Token::ShiftLeft),
+                '<' => Some(if chars.eat('=') { Token::LessEqual } else { Token::Less }),
+                '>' if chars.eat('>') => Some(Token::ShiftRight),
+                '>' => Some(if chars.eat('=') { Token::GreaterEqual } else { Token::Greater }),
+                '&' => Some(if chars.eat('&') { Token::And } else { Token::BitAnd }),
+                '|' => Some(if chars.eat('|') { Token::Or } else { Token::BitOr }),
+                '~' => Some(Token::BitNot),
+                ';' => Some(Token::Terminator),
+                ',' => Some(Token::Comma),
+                '(' => Some(Token::LeftParen),
+                ')' => Some(Token::RightParen),
+                '{' => Some(Token::LeftBrace),
+                '}' => Some(Token::RightBrace),
+                _ if c.is_whitespace() => None,
+                '"' => {
+                    self.state = ScannerState::StringMode;
+                    string(&mut chars, start, line, column, &mut self.errors).map(Token::Str)
+                },
+                'a'..='z' | 'A'..='Z' | '_' => {
+                    self.state = ScannerState::WordMode;
+                    let mut word = c.to_string();
+                    while let Some(next) = chars.peek() {
+                        if !(next.is_ascii_alphanumeric() || next == '_') {
+                            break;
                         }
+                        word.push(next);
+                        chars.next();
+                    }
+                    let word = word.to_ascii_lowercase();
+                    Some(keyword(&word).unwrap_or(Token::Identifier(word)))
+                },
+                '0' if chars.peek_is(|next| radix(next).is_some()) => {
+                    self.state = ScannerState::IntMode;
+                    let radix = chars.next().and_then(radix).unwrap();
+                    // Take the whole run so a stray digit is reported, not split off.
+                    let digits_start = chars.offset;
+                    chars.eat_while(|next| next.is_ascii_alphanumeric() || next == '_');
+                    let text = &self.input[digits_start..chars.offset];
+                    let digits = text.replace('_', "");
+                    match text.char_indices().find(|&(_, d)| d != '_' && !d.is_digit(radix)) {
+                        Some((i, digit)) => {
+                            let offset = digits_start + i;
+                            let span = Span{start: offset, end: offset + 1, line, column: column + (offset - start)};
+                            self.errors.push(ScanError{kind: ScanErrorKind::InvalidDigit{digit, radix}, span});
+                            None
+                        },
+                        None if digits.is_empty() => {
+                            let span = Span{start, end: chars.offset, line, column};
+                            self.errors.push(ScanError{kind: ScanErrorKind::MissingDigits{radix}, span});
+                            None
+                        },
+                        None => Some(integer(&digits, radix)),
                     }
                 },
-                'a'...'z' => Some(Token::Variable(c)),
-                '0'...'9' => {
-                    let mut number = 0;
-                    let mut current = c;
-                    loop {
-                        number *= 10;
-                        number += ((current as u8) - ('0' as u8)) as i64;
-                        match chars.peek() {
-                            Some(&next) if '0' <= next && next <= '9' => {
-                                current = next;
+                '0'..='9' | '.' if c != '.' || chars.peek_is(is_digit) => {
+                    self.state = ScannerState::IntMode;
+                    let mut float = c == '.';
+                    chars.eat_while(is_digit_or_separator);
+                    if !float && chars.peek() == Some('.') && chars.peek_nth_is(1, is_digit) {
+                        chars.next();
+                        chars.eat_while(is_digit_or_separator);
+                        float = true;
+                    }
+                    if chars.peek_is(|next| next == 'e' || next == 'E') {
+                        // The exponent is only part of the number if digits follow it.
+                        let signed = chars.peek_nth_is(1, |next| next == '+' || next == '-');
+                        let digits_at = if signed { 2 } else { 1 };
+                        if chars.peek_nth_is(digits_at, is_digit) {
+                            for _ in 0..digits_at {
                                 chars.next();
                             }
-                            _ => break,
-                        };
+                            chars.eat_while(is_digit_or_separator);
+                            float = true;
+                        }
+                    }
+                    let text = self.input[start..chars.offset].replace('_', "");
+                    if float {
+                        match text.parse::<f64>().unwrap() {
+                            value if value.is_finite() => Some(Token::Float(value)),
+                            _ => {
+                                let span = Span{start, end: chars.offset, line, column};
+                                self.errors.push(ScanError{kind: ScanErrorKind::FloatOutOfRange, span});
+                                None
+                            },
+                        }
+                    } else {
+                        Some(integer(&text, 10))
                     }
-                    Some(Token::Integer(number))
                 },
-                _ => Some(Token::Unknown(c))
+                _ => {
+                    let span = Span{start, end: chars.offset, line, column};
+                    self.errors.push(ScanError{kind: ScanErrorKind::UnexpectedCharacter(c), span});
+                    None
+                },
             };
-            if tok.is_some() {
-                self.output.push(tok.unwrap());
+            if let Some(token) = tok {
+                let span = Span{start, end: chars.offset, line, column};
+                self.output.push(SpannedToken{token, span});
             }
+            self.state = ScannerState::CharMode;
+        }
+        self.state = ScannerState::Done;
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors.clone())
         }
     }
 
-    pub fn output(&self) -> &Vec<Token> {
+    /// The token for the comment at `start..end`, if comments are kept.
+    fn comment(&self, start: usize, end: usize) -> Option<Token> {
+        if self.keep_comments {
+            Some(Token::Comment(self.input[start..end].to_string()))
+        } else {
+            None
+        }
+    }
+
+    pub fn output(&self) -> &Vec<SpannedToken> {
         &self.output
     }
 }
+
+fn is_digit(c: char) -> bool {
+    c.is_ascii_digit()
+}
+
+fn is_digit_or_separator(c: char) -> bool {
+    c.is_ascii_digit() || c == '_'
+}
+
+/// The radix named by the letter after a leading `0`, if any.
+fn radix(c: char) -> Option<u32> {
+    match c {
+        'x' | 'X' => Some(16),
+        'o' | 'O' => Some(8),
+        'b' | 'B' => Some(2),
+        _ => None,
+    }
+}
+
+/// Builds the token for a run of valid `digits`, keeping every digit at full
+/// precision when they are too many for an i64.
+fn integer(digits: &str, radix: u32) -> Token {
+    match i64::from_str_radix(digits, radix) {
+        Ok(number) => Token::Integer(number),
+        Err(_) => Token::BigInteger(BigInt::parse_bytes(digits.as_bytes(), radix).unwrap()),
+    }
+}
+
+/// Reads the rest of a block comment after its opening `/*`, counting nested
+/// ones. Returns whether the comment was closed.
+fn block_comment(chars: &mut Cursor) -> bool {
+    let mut depth = 1;
+    while let Some(c) = chars.next() {
+        if c == '/' && chars.eat('*') {
+            depth += 1;
+        } else if c == '*' && chars.eat('/') {
+            depth -= 1;
+            if depth == 0 {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Reads the rest of a string after its opening quote, which is at `start`.
+/// Every bad escape is reported, but the string is only returned if it had
+/// none and was closed.
+fn string(chars: &mut Cursor, start: usize, line: usize, column: usize, errors: &mut Vec<ScanError>) -> Option<String> {
+    let mut text = String::new();
+    let mut valid = true;
+    loop {
+        let (escape_start, escape_line, escape_column) = (chars.offset, chars.line, chars.column);
+        match chars.next() {
+            Some('"') => return if valid { Some(text) } else { None },
+            Some('\\') => {
+                let escaped = match chars.next() {
+                    Some('n') => Ok('\n'),
+                    Some('t') => Ok('\t'),
+                    Some('"') => Ok('"'),
+                    Some('\\') => Ok('\\'),
+                    Some('u') => unicode_escape(chars).ok_or(ScanErrorKind::InvalidUnicodeEscape),
+                    Some(other) => Err(ScanErrorKind::InvalidEscape(other)),
+                    None => break,
+                };
+                match escaped {
+                    Ok(c) => text.push(c),
+                    Err(kind) => {
+                        let span = Span{start: escape_start, end: chars.offset, line: escape_line, column: escape_column};
+                        errors.push(ScanError{kind, span});
+                        valid = false;
+                    },
+                }
+            },
+            // A CRLF file's line breaks read the same as anyone else's.
+            Some('\r') if chars.peek() == Some('\n') => {},
+            Some(c) => text.push(c),
+            None => break,
+        }
+    }
+    let span = Span{start, end: chars.offset, line, column};
+    errors.push(ScanError{kind: ScanErrorKind::UnterminatedString, span});
+    None
+}
+
+/// Reads the `{...}` of a `\u` escape. A bad escape stops before any
+/// closing quote, so the rest of the string still scans.
+fn unicode_escape(chars: &mut Cursor) -> Option<char> {
+    if !chars.eat('{') {
+        return None;
+    }
+    let mut digits = String::new();
+    while let Some(digit) = chars.peek().filter(char::is_ascii_hexdigit) {
+        digits.push(digit);
+        chars.next();
+    }
+    if !chars.eat('}') || digits.is_empty() || digits.len() > 6 {
+        return None;
+    }
+    u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32)
+}
+
+fn keyword(word: &str) -> Option<Token> {
+    KEYWORDS.iter().find(|&&(name, _)| name == word).map(|(_, token)| token.clone())
+}
+
+impl<'a> Cursor<'a> {
+    fn new(input: &'a str) -> Cursor<'a> {
+        Cursor{
+            chars: input.chars(),
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.peek_nth(0)
+    }
+
+    /// Looks `n` characters past the next one without consuming anything.
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.chars.clone().nth(n)
+    }
+
+    fn peek_is<F: Fn(char) -> bool>(&self, predicate: F) -> bool {
+        self.peek().is_some_and(predicate)
+    }
+
+    fn peek_nth_is<F: Fn(char) -> bool>(&self, n: usize, predicate: F) -> bool {
+        self.peek_nth(n).is_some_and(predicate)
+    }
+
+    /// Consumes the next character if it is `expected`.
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_while<F: Fn(char) -> bool>(&mut self, predicate: F) {
+        while self.peek_is(&predicate) {
+            self.next();
+        }
+    }
+
+    /// Whether the next character ends the line, or there is none.
+    fn at_line_end(&self) -> bool {
+        match self.peek() {
+            None | Some('\n') => true,
+            Some('\r') => self.peek_nth(1) == Some('\n'),
+            _ => false,
+        }
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.offset += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::Integer(value) => write!(f, "integer {}", value),
+            Token::BigInteger(ref value) => write!(f, "integer {}", value),
+            Token::Float(value) => write!(f, "number {:?}", value),
+            Token::Boolean(value) => write!(f, "'{}'", value),
+            Token::Addition => write!(f, "'+'"),
+            Token::Subtraction => write!(f, "'-'"),
+            Token::Multiplication => write!(f, "'*'"),
+            Token::Division => write!(f, "'/'"),
+            Token::Modulus => write!(f, "'%'"),
+            Token::Exponent => write!(f, "'^'"),
+            Token::Assignment => write!(f, "'='"),
+            Token::AdditionAssignment => write!(f, "'+='"),
+            Token::SubtractionAssignment => write!(f, "'-='"),
+            Token::MultiplicationAssignment => write!(f, "'*='"),
+            Token::DivisionAssignment => write!(f, "'/='"),
+            Token::ModulusAssignment => write!(f, "'%='"),
+            Token::ExponentAssignment => write!(f, "'^='"),
+            Token::Equal => write!(f, "'=='"),
+            Token::NotEqual => write!(f, "'!='"),
+            Token::Less => write!(f, "'<'"),
+            Token::LessEqual => write!(f, "'<='"),
+            Token::Greater => write!(f, "'>'"),
+            Token::GreaterEqual => write!(f, "'>='"),
+            Token::And => write!(f, "'&&'"),
+            Token::Or => write!(f, "'||'"),
+            Token::Not => write!(f, "'!'"),
+            Token::BitAnd => write!(f, "'&'"),
+            Token::BitOr => write!(f, "'|'"),
+            Token::BitXor => write!(f, "'^^'"),
+            Token::BitNot => write!(f, "'~'"),
+            Token::ShiftLeft => write!(f, "'<<'"),
+            Token::ShiftRight => write!(f, "'>>'"),
+            Token::Terminator => write!(f, "';'"),
+            Token::Comma => write!(f, "','"),
+            Token::LeftParen => write!(f, "'('"),
+            Token::RightParen => write!(f, "')'"),
+            Token::LeftBrace => write!(f, "'{{'"),
+            Token::RightBrace => write!(f, "'}}'"),
+            Token::Quit => write!(f, "'quit'"),
+            Token::If => write!(f, "'if'"),
+            Token::Else => write!(f, "'else'"),
+            Token::While => write!(f, "'while'"),
+            Token::For => write!(f, "'for'"),
+            Token::To => write!(f, "'to'"),
+            Token::Break => write!(f, "'break'"),
+            Token::Continue => write!(f, "'continue'"),
+            Token::Fn => write!(f, "'fn'"),
+            Token::Return => write!(f, "'return'"),
+            Token::Print => write!(f, "'print'"),
+            Token::Identifier(ref name) => write!(f, "identifier '{}'", name),
+            Token::Str(ref text) => write!(f, "string {:?}", text),
+            Token::Comment(ref text) => write!(f, "comment {:?}", text),
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+impl ScanError {
+    /// Whether more input could still close what was left open.
+    pub fn is_incomplete(&self) -> bool {
+        matches!(self.kind, ScanErrorKind::UnterminatedComment | ScanErrorKind::UnterminatedString)
+    }
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ScanErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character '{}'", c.escape_debug()),
+            ScanErrorKind::InvalidDigit{digit, radix} => write!(f, "invalid digit '{}' in base {} literal", digit, radix),
+            ScanErrorKind::MissingDigits{radix} => write!(f, "base {} literal has no digits", radix),
+            ScanErrorKind::FloatOutOfRange => write!(f, "float literal is out of range"),
+            ScanErrorKind::InvalidEscape(c) => write!(f, "unknown escape '\\{}' in string", c.escape_debug()),
+            ScanErrorKind::InvalidUnicodeEscape => write!(f, "invalid unicode escape in string"),
+            ScanErrorKind::UnterminatedComment => write!(f, "unterminated block comment"),
+            ScanErrorKind::UnterminatedString => write!(f, "unterminated string"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(input: &str) -> Vec<Token> {
+        let mut s = Scanner::new(input);
+        s.scan().unwrap();
+        s.output().iter().map(|spanned| spanned.token.clone()).collect()
+    }
+
+    #[test]
+    fn quit_ignores_case() {
+        for input in &["quit", "Quit", "qUiT", "QUIT"] {
+            assert_eq!(tokens(input), vec![Token::Quit], "scanning {:?}", input);
+        }
+    }
+
+    fn identifier(name: &str) -> Token {
+        Token::Identifier(name.to_string())
+    }
+
+    #[test]
+    fn identifiers_are_lowercased() {
+        assert_eq!(tokens("X"), vec![identifier("x")]);
+        assert_eq!(tokens("Q"), vec![identifier("q")]);
+        assert_eq!(tokens("a B c"), vec![identifier("a"), identifier("b"), identifier("c")]);
+        assert_eq!(tokens("Total"), vec![identifier("total")]);
+    }
+
+    #[test]
+    fn case_does_not_change_the_token_stream() {
+        assert_eq!(tokens("X = 3; Y ^ x; QUIT"), tokens("x = 3; y ^ x; quit"));
+    }
+
+    #[test]
+    fn keywords_need_the_whole_word() {
+        assert_eq!(tokens("qux"), vec![identifier("qux")]);
+        assert_eq!(tokens("quits"), vec![identifier("quits")]);
+        assert_eq!(tokens("quit;x"), vec![Token::Quit, Token::Terminator, identifier("x")]);
+    }
+
+    #[test]
+    fn identifiers_take_digits_and_underscores() {
+        assert_eq!(tokens("x1 _tmp rate_2b"), vec![identifier("x1"), identifier("_tmp"), identifier("rate_2b")]);
+        assert_eq!(tokens("2x"), vec![Token::Integer(2), identifier("x")]);
+    }
+
+    #[test]
+    fn floats_take_fractions_and_exponents() {
+        assert_eq!(tokens("2.75 .5 1e-9 6.02E23 2e+3"),
+                   vec![Token::Float(2.75), Token::Float(0.5), Token::Float(1e-9), Token::Float(6.02e23), Token::Float(2e3)]);
+        assert_eq!(tokens("42"), vec![Token::Integer(42)]);
+    }
+
+    #[test]
+    fn an_exponent_needs_digits() {
+        assert_eq!(tokens("2e"), vec![Token::Integer(2), identifier("e")]);
+        assert_eq!(tokens("2e+x"), vec![Token::Integer(2), identifier("e"), Token::Addition, identifier("x")]);
+        assert!(Scanner::new("3.").scan().is_err());
+    }
+
+    #[test]
+    fn floats_must_be_finite() {
+        let errors = Scanner::new("x = 1.5e400;").scan().unwrap_err();
+        assert_eq!(errors, vec![ScanError{kind: ScanErrorKind::FloatOutOfRange, span: Span{start: 4, end: 11, line: 1, column: 5}}]);
+        assert_eq!(tokens("1e-400 1.7976931348623157e308"), vec![Token::Float(0.0), Token::Float(f64::MAX)]);
+    }
+
+    #[test]
+    fn integers_take_radix_prefixes_and_separators() {
+        assert_eq!(tokens("0x1F 0o755 0b1010 1_000_000 0XfF_fF"),
+                   vec![Token::Integer(31), Token::Integer(0o755), Token::Integer(10), Token::Integer(1_000_000), Token::Integer(0xffff)]);
+        assert_eq!(tokens("0xFFFFFFFFFFFFFFFF"), vec![Token::BigInteger(BigInt::from(u64::MAX))]);
+    }
+
+    #[test]
+    fn digits_must_belong_to_the_radix() {
+        let errors = Scanner::new("x = 0b1021;").scan().unwrap_err();
+        assert_eq!(errors, vec![ScanError{kind: ScanErrorKind::InvalidDigit{digit: '2', radix: 2},
+                                          span: Span{start: 8, end: 9, line: 1, column: 9}}]);
+        let errors = Scanner::new("0x").scan().unwrap_err();
+        assert_eq!(errors[0].kind, ScanErrorKind::MissingDigits{radix: 16});
+    }
+
+    #[test]
+    fn comparison_and_boolean_operators_take_two_characters() {
+        assert_eq!(tokens("a == b != c <= d >= e"),
+                   vec![identifier("a"), Token::Equal, identifier("b"), Token::NotEqual, identifier("c"),
+                        Token::LessEqual, identifier("d"), Token::GreaterEqual, identifier("e")]);
+        assert_eq!(tokens("a=b<c>!d&&e||TRUE"),
+                   vec![identifier("a"), Token::Assignment, identifier("b"), Token::Less, identifier("c"), Token::Greater,
+                        Token::Not, identifier("d"), Token::And, identifier("e"), Token::Or, Token::Boolean(true)]);
+    }
+
+    #[test]
+    fn bitwise_operators_are_told_apart_from_their_prefixes() {
+        assert_eq!(tokens("a & b && c | d || e"),
+                   vec![identifier("a"), Token::BitAnd, identifier("b"), Token::And, identifier("c"),
+                        Token::BitOr, identifier("d"), Token::Or, identifier("e")]);
+        assert_eq!(tokens("1<<2>>3<=4^^5^6~7"),
+                   vec![Token::Integer(1), Token::ShiftLeft, Token::Integer(2), Token::ShiftRight, Token::Integer(3),
+                        Token::LessEqual, Token::Integer(4), Token::BitXor, Token::Integer(5), Token::Exponent,
+                        Token::Integer(6), Token::BitNot, Token::Integer(7)]);
+    }
+
+    #[test]
+    fn compound_assignments_are_single_tokens() {
+        assert_eq!(tokens("+= -= *= /= %= ^= ^^ ="),
+                   vec![Token::AdditionAssignment, Token::SubtractionAssignment, Token::MultiplicationAssignment,
+                        Token::DivisionAssignment, Token::ModulusAssignment, Token::ExponentAssignment,
+                        Token::BitXor, Token::Assignment]);
+        assert_eq!(tokens("x*=-1"), vec![identifier("x"), Token::MultiplicationAssignment, Token::Subtraction, Token::Integer(1)]);
+    }
+
+    #[test]
+    fn function_definitions_take_commas_and_keywords() {
+        assert_eq!(tokens("FN f(a, b) { return a; }"),
+                   vec![Token::Fn, identifier("f"), Token::LeftParen, identifier("a"), Token::Comma, identifier("b"),
+                        Token::RightParen, Token::LeftBrace, Token::Return, identifier("a"), Token::Terminator,
+                        Token::RightBrace]);
+    }
+
+    #[test]
+    fn strings_take_escapes() {
+        assert_eq!(tokens(r#"print "total = ", t;"#),
+                   vec![Token::Print, Token::Str("total = ".to_string()), Token::Comma, identifier("t"), Token::Terminator]);
+        assert_eq!(tokens(r#""a\tb\n\"q\" \\ Ünï""#), vec![Token::Str("a\tb\n\"q\" \\ Ünï".to_string())]);
+        assert_eq!(tokens(r#""""#), vec![Token::Str(String::new())]);
+        assert_eq!(tokens(r#""\u{41}\u{e9}\u{1F600}""#), vec![Token::Str("Aé\u{1F600}".to_string())]);
+    }
+
+    #[test]
+    fn bad_strings_are_reported_with_their_span() {
+        let errors = Scanner::new(r#"x "a\qb" "c"#).scan().unwrap_err();
+        assert_eq!(errors, vec![ScanError{kind: ScanErrorKind::InvalidEscape('q'), span: Span{start: 4, end: 6, line: 1, column: 5}},
+                                ScanError{kind: ScanErrorKind::UnterminatedString, span: Span{start: 9, end: 11, line: 1, column: 10}}]);
+        assert!(!errors[0].is_incomplete());
+        assert!(errors[1].is_incomplete());
+        for input in &[r#""\u{D800}""#, r#""\u{110000}""#, r#""\u{}""#, r#""\u41""#, r#""\u{0000041}""#, r#""\u{4G}""#] {
+            let errors = Scanner::new(input).scan().unwrap_err();
+            assert_eq!(errors.len(), 1, "scanning {}", input);
+            assert_eq!(errors[0].kind, ScanErrorKind::InvalidUnicodeEscape, "scanning {}", input);
+        }
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        assert_eq!(tokens("x = 1; # one\ny // two\n/ 2 /= 3"),
+                   vec![identifier("x"), Token::Assignment, Token::Integer(1), Token::Terminator, identifier("y"),
+                        Token::Division, Token::Integer(2), Token::DivisionAssignment, Token::Integer(3)]);
+        assert_eq!(tokens("1 /* a /* nested */ still * / comment */ + 2"), vec![Token::Integer(1), Token::Addition, Token::Integer(2)]);
+        assert_eq!(tokens("# only a comment"), vec![]);
+    }
+
+    #[test]
+    fn comments_can_be_kept_as_trivia() {
+        let mut s = Scanner::new("x /* a */ # b");
+        s.keep_comments(true);
+        s.scan().unwrap();
+        let output: Vec<Token> = s.output().iter().map(|spanned| spanned.token.clone()).collect();
+        assert_eq!(output, vec![identifier("x"), Token::Comment("/* a */".to_string()), Token::Comment("# b".to_string())]);
+        assert_eq!(s.output()[1].span, Span{start: 2, end: 9, line: 1, column: 3});
+    }
+
+    #[test]
+    fn an_unclosed_block_comment_is_reported_at_its_start() {
+        let errors = Scanner::new("1 /* a /* b */").scan().unwrap_err();
+        assert_eq!(errors, vec![ScanError{kind: ScanErrorKind::UnterminatedComment, span: Span{start: 2, end: 4, line: 1, column: 3}}]);
+        assert!(errors[0].is_incomplete());
+    }
+
+    #[test]
+    fn any_whitespace_separates_tokens() {
+        assert_eq!(tokens("x\t=\n1\r\n+\u{a0}2\u{2003};"),
+                   vec![identifier("x"), Token::Assignment, Token::Integer(1), Token::Addition, Token::Integer(2), Token::Terminator]);
+    }
+
+    #[test]
+    fn spans_count_lines_the_same_with_crlf() {
+        for input in &["a\n\tb # c\n  d", "a\r\n\tb # c\r\n  d"] {
+            let mut s = Scanner::new(input);
+            s.keep_comments(true);
+            s.scan().unwrap();
+            let positions: Vec<(usize, usize)> = s.output().iter().map(|spanned| (spanned.span.line, spanned.span.column)).collect();
+            assert_eq!(positions, vec![(1, 1), (2, 2), (2, 4), (3, 3)], "scanning {:?}", input);
+            assert_eq!(s.output()[2].token, Token::Comment("# c".to_string()));
+        }
+        assert_eq!(tokens("\"a\r\nb\""), vec![Token::Str("a\nb".to_string())]);
+    }
+
+    #[test]
+    fn spans_cover_the_original_spelling() {
+        let mut s = Scanner::new("QuIt");
+        s.scan().unwrap();
+        assert_eq!(s.output()[0].span, Span{start: 0, end: 4, line: 1, column: 1});
+    }
+}
@@ -0,0 +1,533 @@
+//! Runtime values of CalcLang and the arithmetic on them.
+//!
+//! Integers are held as an `i64` while they fit and move to an arbitrary
+//! precision `BigInt` when a literal or a result does not, so arithmetic is
+//! always exact. Division truncates toward zero and the remainder takes the
+//! sign of the dividend, so that `a == (a / b) * b + a % b` always holds. A
+//! negative exponent is treated as `1 / (base ^ -exponent)` with the same
+//! truncating division.
+//!
+//! Floats follow IEEE 754 double precision. When either operand is a float
+//! the other is converted and the float operation is used, so `/` gives a
+//! fractional result. Dividing by zero is an error for floats as well, and
+//! a result that is infinite or not a number is reported rather than
+//! returned.
+//!
+//! In `NumericMode::Rational`, dividing integers and raising them to a
+//! negative power give an exact fraction instead of truncating. Fractions
+//! are kept in lowest terms, stay exact through `+ - * / % ^` with integer
+//! exponents, and turn back into integers whenever the denominator is 1.
+//!
+//! Comparisons work across all the numeric kinds and produce booleans.
+//! Booleans only take part in `==`, `!=`, `&&`, `||` and `!`; there is no
+//! implicit conversion between booleans and numbers.
+//!
+//! Bitwise operators apply to integers only and treat them as two's
+//! complement numbers of unlimited width, so `~x` is `-x - 1`. Shifts are
+//! likewise unbounded: `x << n` is `x * 2 ^ n` however large `n` is, and
+//! `x >> n` rounds toward negative infinity. A negative shift count is an
+//! error.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Pow, Signed, ToPrimitive, Zero};
+
+use ast::{BinaryOperator, UnaryOperator};
+use interpreter::RuntimeError;
+
+/// The largest result, in bits, that exponentiation may produce.
+const MAX_POWER_BITS: u64 = 1 << 20;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    BigInteger(BigInt),
+    Float(f64),
+    Rational(BigRational),
+    Boolean(bool),
+}
+
+/// What dividing one integer by another produces.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NumericMode {
+    /// A truncated integer quotient.
+    Integer,
+    /// An exact fraction.
+    Rational,
+}
+
+impl Value {
+    /// Wraps `value`, using the `i64` representation when it fits.
+    pub fn from_big(value: BigInt) -> Value {
+        match value.to_i64() {
+            Some(small) => Value::Integer(small),
+            None => Value::BigInteger(value),
+        }
+    }
+
+    /// Wraps `value`, as an integer when its denominator is 1.
+    pub fn from_rational(value: BigRational) -> Value {
+        if value.is_integer() {
+            Value::from_big(value.to_integer())
+        } else {
+            Value::Rational(value)
+        }
+    }
+
+    /// Formats the value for output. Fractions are written as `n/d`, or as
+    /// a decimal rounded to `places` digits after the point when given.
+    pub fn format(&self, places: Option<usize>) -> String {
+        match (self, places) {
+            (Value::Rational(value), Some(places)) => decimal(value, places),
+            _ => self.to_string(),
+        }
+    }
+
+    /// Wraps a float result, reporting infinities and NaN as errors.
+    pub fn from_float(value: f64) -> Result<Value, RuntimeError> {
+        if value.is_nan() {
+            Err(RuntimeError::NotANumber)
+        } else if value.is_infinite() {
+            Err(RuntimeError::Overflow)
+        } else {
+            Ok(Value::Float(value))
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match *self {
+            Value::Integer(_) | Value::BigInteger(_) => "integer",
+            Value::Float(_) => "float",
+            Value::Rational(_) => "fraction",
+            Value::Boolean(_) => "boolean",
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(*self, Value::Integer(_) | Value::BigInteger(_))
+    }
+
+    pub fn is_number(&self) -> bool {
+        !matches!(*self, Value::Boolean(_))
+    }
+
+    pub fn to_big(&self) -> BigInt {
+        match *self {
+            Value::Integer(value) => BigInt::from(value),
+            Value::BigInteger(ref value) => value.clone(),
+            _ => unreachable!("only integers convert to BigInt"),
+        }
+    }
+
+    pub fn to_float(&self) -> f64 {
+        match *self {
+            Value::Integer(value) => value as f64,
+            Value::BigInteger(ref value) => value.to_f64().unwrap_or(f64::NAN),
+            Value::Float(value) => value,
+            Value::Rational(ref value) => value.to_f64().unwrap_or(f64::NAN),
+            Value::Boolean(_) => unreachable!("booleans do not convert to numbers"),
+        }
+    }
+
+    pub fn to_rational(&self) -> BigRational {
+        match *self {
+            Value::Rational(ref value) => value.clone(),
+            _ => BigRational::from_integer(self.to_big()),
+        }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        match *self {
+            Value::Integer(value) => value < 0,
+            Value::BigInteger(ref value) => value.is_negative(),
+            Value::Float(value) => value < 0.0,
+            Value::Rational(ref value) => value.is_negative(),
+            Value::Boolean(_) => false,
+        }
+    }
+}
+
+pub fn unary(op: UnaryOperator, operand: &Value) -> Result<Value, RuntimeError> {
+    Ok(match (op, operand) {
+        (UnaryOperator::Not, &Value::Boolean(value)) => Value::Boolean(!value),
+        (UnaryOperator::BitNot, &Value::Integer(value)) => Value::Integer(!value),
+        (UnaryOperator::BitNot, Value::BigInteger(value)) => Value::from_big(-value - 1),
+        (UnaryOperator::Not, _) | (UnaryOperator::BitNot, _) | (_, &Value::Boolean(_)) => {
+            let message = format!("'{}' cannot be applied to {}", op, operand.type_name());
+            return Err(RuntimeError::TypeMismatch(message));
+        },
+        (UnaryOperator::Plus, _) => operand.clone(),
+        // Negating i64::MIN does not fit, so it moves to a BigInt.
+        (UnaryOperator::Negation, &Value::Integer(value)) => match value.checked_neg() {
+            Some(negated) => Value::Integer(negated),
+            None => Value::BigInteger(-BigInt::from(value)),
+        },
+        (UnaryOperator::Negation, Value::BigInteger(value)) => Value::from_big(-value),
+        (UnaryOperator::Negation, &Value::Float(value)) => Value::Float(-value),
+        (UnaryOperator::Negation, Value::Rational(value)) => Value::Rational(-value),
+    })
+}
+
+/// Applies `op` to two values. `&&` and `||` are applied to both operands
+/// here; short-circuiting is left to the caller.
+pub fn binary(op: BinaryOperator, left: &Value, right: &Value, mode: NumericMode) -> Result<Value, RuntimeError> {
+    let result = match op {
+        BinaryOperator::Equal => equals(op, left, right)?,
+        BinaryOperator::NotEqual => !equals(op, left, right)?,
+        BinaryOperator::Less => compare(op, left, right)? == Ordering::Less,
+        BinaryOperator::LessEqual => compare(op, left, right)? != Ordering::Greater,
+        BinaryOperator::Greater => compare(op, left, right)? == Ordering::Greater,
+        BinaryOperator::GreaterEqual => compare(op, left, right)? != Ordering::Less,
+        BinaryOperator::And | BinaryOperator::Or => match (left, right) {
+            (&Value::Boolean(left), &Value::Boolean(right)) =>
+                if op == BinaryOperator::And { left && right } else { left || right },
+            _ => return Err(mismatch(op, left, right)),
+        },
+        BinaryOperator::BitAnd | BinaryOperator::BitOr | BinaryOperator::BitXor |
+        BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => return bitwise(op, left, right),
+        _ => return arithmetic(op, left, right, mode),
+    };
+    Ok(Value::Boolean(result))
+}
+
+fn mismatch(op: BinaryOperator, left: &Value, right: &Value) -> RuntimeError {
+    let message = format!("'{}' cannot be applied to {} and {}", op, left.type_name(), right.type_name());
+    RuntimeError::TypeMismatch(message)
+}
+
+fn equals(op: BinaryOperator, left: &Value, right: &Value) -> Result<bool, RuntimeError> {
+    match (left, right) {
+        (&Value::Boolean(left), &Value::Boolean(right)) => Ok(left == right),
+        _ => compare(op, left, right).map(|ordering| ordering == Ordering::Equal),
+    }
+}
+
+/// Orders two numbers exactly, unless one is a float, in which case both
+/// are compared as floats.
+fn compare(op: BinaryOperator, left: &Value, right: &Value) -> Result<Ordering, RuntimeError> {
+    if !left.is_number() || !right.is_number() {
+        return Err(mismatch(op, left, right));
+    }
+    Ok(match (left, right) {
+        (&Value::Integer(left), &Value::Integer(right)) => left.cmp(&right),
+        (&Value::Float(_), _) | (_, &Value::Float(_)) =>
+            left.to_float().partial_cmp(&right.to_float()).unwrap_or(Ordering::Equal),
+        _ => left.to_rational().cmp(&right.to_rational()),
+    })
+}
+
+fn bitwise(op: BinaryOperator, left: &Value, right: &Value) -> Result<Value, RuntimeError> {
+    if !left.is_integer() || !right.is_integer() {
+        return Err(mismatch(op, left, right));
+    }
+    if let (&Value::Integer(left), &Value::Integer(right)) = (left, right) {
+        match op {
+            BinaryOperator::BitAnd => return Ok(Value::Integer(left & right)),
+            BinaryOperator::BitOr => return Ok(Value::Integer(left | right)),
+            BinaryOperator::BitXor => return Ok(Value::Integer(left ^ right)),
+            _ => {},
+        }
+    }
+    let (left, right) = (left.to_big(), right.to_big());
+    let result = match op {
+        BinaryOperator::BitAnd => left & right,
+        BinaryOperator::BitOr => left | right,
+        BinaryOperator::BitXor => left ^ right,
+        _ if right.is_negative() => return Err(RuntimeError::NegativeShift),
+        BinaryOperator::ShiftLeft if left.is_zero() => left,
+        BinaryOperator::ShiftLeft => match right.to_u64() {
+            Some(count) if count <= MAX_POWER_BITS => left << count,
+            _ => return Err(RuntimeError::Overflow),
+        },
+        // Shifting out every bit leaves only the sign.
+        BinaryOperator::ShiftRight => match right.to_u64() {
+            Some(count) => left >> count,
+            None if left.is_negative() => -BigInt::one(),
+            None => BigInt::zero(),
+        },
+        _ => unreachable!("not a bitwise operator"),
+    };
+    Ok(Value::from_big(result))
+}
+
+fn arithmetic(op: BinaryOperator, left: &Value, right: &Value, mode: NumericMode) -> Result<Value, RuntimeError> {
+    if !left.is_number() || !right.is_number() {
+        return Err(mismatch(op, left, right));
+    }
+    if let (&Value::Float(_), _) | (_, &Value::Float(_)) = (left, right) {
+        return float_binary(op, left.to_float(), right.to_float()).map(Value::Float);
+    }
+    let fraction = matches!((left, right), (&Value::Rational(_), _) | (_, &Value::Rational(_)));
+    let exact_division = mode == NumericMode::Rational
+        && (op == BinaryOperator::Division || op == BinaryOperator::Exponent && right.is_negative());
+    if fraction || exact_division {
+        return rational_binary(op, left.to_rational(), right.to_rational());
+    }
+    if let (&Value::Integer(left), &Value::Integer(right)) = (left, right) {
+        if let Some(result) = small_binary(op, left, right)? {
+            return Ok(Value::Integer(result));
+        }
+    }
+    big_binary(op, left.to_big(), right.to_big()).map(Value::from_big)
+}
+
+/// Applies `op` on `i64`, giving `None` when the result does not fit.
+fn small_binary(op: BinaryOperator, left: i64, right: i64) -> Result<Option<i64>, RuntimeError> {
+    Ok(match op {
+        BinaryOperator::Addition => left.checked_add(right),
+        BinaryOperator::Subtraction => left.checked_sub(right),
+        BinaryOperator::Multiplication => left.checked_mul(right),
+        BinaryOperator::Division => {
+            if right == 0 {
+                return Err(RuntimeError::DivisionByZero);
+            }
+            left.checked_div(right)
+        },
+        BinaryOperator::Modulus => {
+            if right == 0 {
+                return Err(RuntimeError::DivisionByZero);
+            }
+            // i64::MIN % -1 is mathematically 0 but overflows checked_rem.
+            Some(left.wrapping_rem(right))
+        },
+        BinaryOperator::Exponent if right >= 0 && right <= i64::from(u32::MAX) => left.checked_pow(right as u32),
+        BinaryOperator::Exponent => None,
+        _ => unreachable!("not an arithmetic operator"),
+    })
+}
+
+fn big_binary(op: BinaryOperator, left: BigInt, right: BigInt) -> Result<BigInt, RuntimeError> {
+    match op {
+        BinaryOperator::Addition => Ok(left + right),
+        BinaryOperator::Subtraction => Ok(left - right),
+        BinaryOperator::Multiplication => Ok(left * right),
+        BinaryOperator::Division if right.is_zero() => Err(RuntimeError::DivisionByZero),
+        BinaryOperator::Division => Ok(left / right),
+        BinaryOperator::Modulus if right.is_zero() => Err(RuntimeError::DivisionByZero),
+        BinaryOperator::Modulus => Ok(left % right),
+        BinaryOperator::Exponent => big_power(left, right),
+        _ => unreachable!("not an arithmetic operator"),
+    }
+}
+
+fn float_binary(op: BinaryOperator, left: f64, right: f64) -> Result<f64, RuntimeError> {
+    let result = match op {
+        BinaryOperator::Addition => left + right,
+        BinaryOperator::Subtraction => left - right,
+        BinaryOperator::Multiplication => left * right,
+        BinaryOperator::Division | BinaryOperator::Modulus if right == 0.0 => return Err(RuntimeError::DivisionByZero),
+        BinaryOperator::Division => left / right,
+        BinaryOperator::Modulus => left % right,
+        BinaryOperator::Exponent => left.powf(right),
+        _ => unreachable!("not an arithmetic operator"),
+    };
+    if result.is_nan() {
+        Err(RuntimeError::NotANumber)
+    } else if result.is_infinite() {
+        Err(RuntimeError::Overflow)
+    } else {
+        Ok(result)
+    }
+}
+
+fn rational_binary(op: BinaryOperator, left: BigRational, right: BigRational) -> Result<Value, RuntimeError> {
+    let result = match op {
+        BinaryOperator::Addition => left + right,
+        BinaryOperator::Subtraction => left - right,
+        BinaryOperator::Multiplication => left * right,
+        BinaryOperator::Division | BinaryOperator::Modulus if right.is_zero() => return Err(RuntimeError::DivisionByZero),
+        BinaryOperator::Division => left / right,
+        BinaryOperator::Modulus => {
+            let quotient = (&left / &right).trunc();
+            left - right * quotient
+        },
+        BinaryOperator::Exponent if right.is_integer() => rational_power(left, right.to_integer())?,
+        // A fractional power is generally irrational, so leave exact arithmetic.
+        BinaryOperator::Exponent => {
+            let left = left.to_f64().unwrap_or(f64::NAN);
+            let right = right.to_f64().unwrap_or(f64::NAN);
+            return float_binary(op, left, right).map(Value::Float);
+        },
+        _ => unreachable!("not an arithmetic operator"),
+    };
+    Ok(Value::from_rational(result))
+}
+
+fn rational_power(base: BigRational, exponent: BigInt) -> Result<BigRational, RuntimeError> {
+    if base.is_zero() && exponent.is_negative() {
+        return Err(RuntimeError::DivisionByZero);
+    }
+    let (numer, denom) = if exponent.is_negative() {
+        (base.denom().clone(), base.numer().clone())
+    } else {
+        (base.numer().clone(), base.denom().clone())
+    };
+    let exponent = exponent.abs();
+    let numer = big_power(numer, exponent.clone())?;
+    let denom = big_power(denom, exponent)?;
+    Ok(BigRational::new(numer, denom))
+}
+
+fn big_power(base: BigInt, exponent: BigInt) -> Result<BigInt, RuntimeError> {
+    let odd = !(&exponent % 2u32).is_zero();
+    if base.is_zero() {
+        return if exponent.is_negative() {
+            Err(RuntimeError::DivisionByZero)
+        } else if exponent.is_zero() {
+            Ok(BigInt::one())
+        } else {
+            Ok(base)
+        };
+    }
+    if base.is_one() {
+        return Ok(base);
+    }
+    if base == -BigInt::one() {
+        return Ok(if odd { base } else { BigInt::one() });
+    }
+    if exponent.is_negative() {
+        return Ok(BigInt::zero());
+    }
+    match exponent.to_u64() {
+        Some(exponent) if exponent.saturating_mul(base.bits()) <= MAX_POWER_BITS => Ok(base.pow(exponent)),
+        _ => Err(RuntimeError::Overflow),
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Integer(value) => write!(f, "{}", value),
+            Value::BigInteger(ref value) => write!(f, "{}", value),
+            // Debug formatting keeps the decimal point on whole floats.
+            Value::Float(value) => write!(f, "{:?}", value),
+            Value::Rational(ref value) => write!(f, "{}", value),
+            Value::Boolean(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Writes `value` in decimal, rounded half away from zero to `places` digits
+/// after the point.
+fn decimal(value: &BigRational, places: usize) -> String {
+    let scale = BigRational::from_integer(Pow::pow(BigInt::from(10), places));
+    let scaled = (value * scale).round().to_integer();
+    let mut digits = scaled.abs().to_string();
+    if digits.len() <= places {
+        digits = format!("{}{}", "0".repeat(places + 1 - digits.len()), digits);
+    }
+    if places > 0 {
+        let point = digits.len() - places;
+        digits.insert(point, '.');
+    }
+    if scaled.is_negative() {
+        digits.insert(0, '-');
+    }
+    digits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(digits: &str) -> Value {
+        Value::BigInteger(digits.parse().unwrap())
+    }
+
+    fn integer_mode(op: BinaryOperator, left: Value, right: Value) -> Result<Value, RuntimeError> {
+        binary(op, &left, &right, NumericMode::Integer)
+    }
+
+    #[test]
+    fn integers_move_to_bigint_when_they_overflow() {
+        assert_eq!(integer_mode(BinaryOperator::Exponent, Value::Integer(2), Value::Integer(200)),
+                   Ok(big("1606938044258990275541962092341162602522202993782792835301376")));
+        assert_eq!(integer_mode(BinaryOperator::Addition, Value::Integer(i64::MAX), Value::Integer(1)), Ok(big("9223372036854775808")));
+        assert_eq!(integer_mode(BinaryOperator::Multiplication, Value::Integer(i64::MIN), Value::Integer(2)), Ok(big("-18446744073709551616")));
+        assert_eq!(integer_mode(BinaryOperator::Division, Value::Integer(i64::MIN), Value::Integer(-1)), Ok(big("9223372036854775808")));
+        assert_eq!(integer_mode(BinaryOperator::Modulus, Value::Integer(i64::MIN), Value::Integer(-1)), Ok(Value::Integer(0)));
+        assert_eq!(unary(UnaryOperator::Negation, &Value::Integer(i64::MIN)), Ok(big("9223372036854775808")));
+    }
+
+    #[test]
+    fn bigint_results_that_fit_return_to_i64() {
+        assert_eq!(integer_mode(BinaryOperator::Subtraction, big("9223372036854775808"), Value::Integer(1)), Ok(Value::Integer(i64::MAX)));
+        assert_eq!(integer_mode(BinaryOperator::Division, big("18446744073709551616"), big("9223372036854775808")), Ok(Value::Integer(2)));
+    }
+
+    #[test]
+    fn integer_division_truncates_toward_zero() {
+        assert_eq!(integer_mode(BinaryOperator::Division, Value::Integer(-7), Value::Integer(2)), Ok(Value::Integer(-3)));
+        assert_eq!(integer_mode(BinaryOperator::Modulus, Value::Integer(-7), Value::Integer(2)), Ok(Value::Integer(-1)));
+        assert_eq!(integer_mode(BinaryOperator::Division, Value::Integer(1), Value::Integer(0)), Err(RuntimeError::DivisionByZero));
+        assert_eq!(integer_mode(BinaryOperator::Exponent, Value::Integer(2), Value::Integer(-1)), Ok(Value::Integer(0)));
+    }
+
+    fn fraction(numer: i64, denom: i64) -> Value {
+        Value::Rational(BigRational::new(numer.into(), denom.into()))
+    }
+
+    fn rational_mode(op: BinaryOperator, left: Value, right: Value) -> Result<Value, RuntimeError> {
+        binary(op, &left, &right, NumericMode::Rational)
+    }
+
+    #[test]
+    fn rational_division_is_exact_and_normalized() {
+        assert_eq!(rational_mode(BinaryOperator::Division, Value::Integer(6), Value::Integer(-4)), Ok(fraction(-3, 2)));
+        assert_eq!(rational_mode(BinaryOperator::Division, Value::Integer(6), Value::Integer(3)), Ok(Value::Integer(2)));
+        assert_eq!(rational_mode(BinaryOperator::Addition, fraction(1, 3), fraction(2, 3)), Ok(Value::Integer(1)));
+        assert_eq!(rational_mode(BinaryOperator::Exponent, Value::Integer(2), Value::Integer(-3)), Ok(fraction(1, 8)));
+        assert_eq!(rational_mode(BinaryOperator::Modulus, fraction(7, 2), Value::Integer(-2)), Ok(fraction(3, 2)));
+        assert_eq!(rational_mode(BinaryOperator::Division, fraction(1, 2), Value::Integer(0)), Err(RuntimeError::DivisionByZero));
+        // Fractions stay exact even when the mode no longer makes new ones.
+        assert_eq!(integer_mode(BinaryOperator::Multiplication, fraction(1, 3), Value::Integer(3)), Ok(Value::Integer(1)));
+        assert_eq!(integer_mode(BinaryOperator::Less, fraction(1, 3), Value::Float(0.34)), Ok(Value::Boolean(true)));
+    }
+
+    #[test]
+    fn fractions_print_as_rounded_decimals() {
+        assert_eq!(fraction(2, 3).format(None), "2/3");
+        assert_eq!(fraction(2, 3).format(Some(4)), "0.6667");
+        assert_eq!(fraction(-1, 8).format(Some(2)), "-0.13");
+        assert_eq!(fraction(5, 2).format(Some(0)), "3");
+        assert_eq!(fraction(1, 400).format(Some(2)), "0.00");
+        assert_eq!(fraction(-1, 400).format(Some(2)), "0.00");
+        assert_eq!(Value::Integer(7).format(Some(2)), "7");
+    }
+
+    #[test]
+    fn shifts_are_unbounded() {
+        assert_eq!(integer_mode(BinaryOperator::ShiftLeft, Value::Integer(1), Value::Integer(64)), Ok(big("18446744073709551616")));
+        assert_eq!(integer_mode(BinaryOperator::ShiftLeft, Value::Integer(-3), Value::Integer(1)), Ok(Value::Integer(-6)));
+        assert_eq!(integer_mode(BinaryOperator::ShiftRight, big("18446744073709551616"), Value::Integer(64)), Ok(Value::Integer(1)));
+        assert_eq!(integer_mode(BinaryOperator::ShiftRight, Value::Integer(-5), Value::Integer(1)), Ok(Value::Integer(-3)));
+        assert_eq!(integer_mode(BinaryOperator::ShiftRight, Value::Integer(5), Value::Integer(64)), Ok(Value::Integer(0)));
+        assert_eq!(integer_mode(BinaryOperator::ShiftRight, Value::Integer(-5), big("100000000000000000000")), Ok(Value::Integer(-1)));
+        assert_eq!(integer_mode(BinaryOperator::ShiftLeft, Value::Integer(0), big("100000000000000000000")), Ok(Value::Integer(0)));
+        assert_eq!(integer_mode(BinaryOperator::ShiftLeft, Value::Integer(1), big("100000000000000000000")), Err(RuntimeError::Overflow));
+    }
+
+    #[test]
+    fn negative_shifts_are_errors() {
+        assert_eq!(integer_mode(BinaryOperator::ShiftLeft, Value::Integer(1), Value::Integer(-1)), Err(RuntimeError::NegativeShift));
+        assert_eq!(integer_mode(BinaryOperator::ShiftRight, Value::Integer(1), Value::Integer(-1)), Err(RuntimeError::NegativeShift));
+    }
+
+    #[test]
+    fn bitwise_operators_use_twos_complement() {
+        assert_eq!(integer_mode(BinaryOperator::BitAnd, Value::Integer(-1), big("18446744073709551616")), Ok(big("18446744073709551616")));
+        assert_eq!(integer_mode(BinaryOperator::BitXor, Value::Integer(6), Value::Integer(3)), Ok(Value::Integer(5)));
+        assert_eq!(unary(UnaryOperator::BitNot, &Value::Integer(0)), Ok(Value::Integer(-1)));
+        assert_eq!(unary(UnaryOperator::BitNot, &big("18446744073709551616")), Ok(big("-18446744073709551617")));
+        assert!(integer_mode(BinaryOperator::BitOr, Value::Integer(1), Value::Float(1.0)).is_err());
+    }
+
+    #[test]
+    fn huge_powers_are_refused() {
+        assert_eq!(integer_mode(BinaryOperator::Exponent, Value::Integer(2), big("100000000000000000000")), Err(RuntimeError::Overflow));
+        assert_eq!(integer_mode(BinaryOperator::Exponent, Value::Integer(-1), big("100000000000000000001")), Ok(Value::Integer(-1)));
+    }
+}